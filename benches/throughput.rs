@@ -5,8 +5,6 @@ use criterion::BenchmarkId;
 use criterion::Criterion;
 use criterion::Throughput;
 
-use std::convert::TryInto;
-
 use lfq::*;
 use std::thread;
 
@@ -31,8 +29,7 @@ impl DummyThreadManager {
     }
 
     fn join_all(mut self) {
-        (&mut self)
-            .handles
+        self.handles
             .iter_mut()
             .for_each(|(_h, b)| b.store(false, SeqCst));
         self.handles
@@ -41,6 +38,7 @@ impl DummyThreadManager {
     }
 }
 
+#[allow(dead_code)]
 #[derive(Default, Copy, Debug, Clone)]
 struct DataDummy(u64, f64, f64, f64);
 const DATA_DEFAULT: DataDummy = DataDummy(1233123, 30912831.132213, -12931.123, 123.98);
@@ -89,9 +87,135 @@ fn single_producer(c: &mut Criterion) {
     g.finish()
 }
 
+struct AdjacentClients<C> {
+    a: C,
+    b: C,
+}
+
+fn run_adjacent<C>(clients: &mut AdjacentClients<C>, iters: u64) -> std::time::Duration
+where
+    C: std::ops::DerefMut<Target = QueueClient<DataDummy>> + Send,
+{
+    let start = Instant::now();
+    thread::scope(|s| {
+        for c in [&mut clients.a, &mut clients.b] {
+            s.spawn(move || {
+                for _ in 0..iters {
+                    c.push(DATA_DEFAULT);
+                    black_box(c.next());
+                }
+            });
+        }
+    });
+    start.elapsed()
+}
+
+struct Unpadded<T>(T);
+
+impl<T> std::ops::Deref for Unpadded<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> std::ops::DerefMut for Unpadded<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+fn adjacent_clients(c: &mut Criterion) {
+    let mut g = c.benchmark_group("Adjacent Clients");
+    const QSIZE: usize = 128;
+
+    g.bench_function("unpadded", |b| {
+        b.iter_custom(|iters| {
+            let mut clients = AdjacentClients {
+                a: Unpadded(QueueClient::new_queue(QSIZE)),
+                b: Unpadded(QueueClient::new_queue(QSIZE)),
+            };
+            run_adjacent(&mut clients, iters)
+        })
+    });
+    g.bench_function("padded", |b| {
+        b.iter_custom(|iters| {
+            let mut clients = AdjacentClients {
+                a: CachePadded::new(QueueClient::new_queue(QSIZE)),
+                b: CachePadded::new(QueueClient::new_queue(QSIZE)),
+            };
+            run_adjacent(&mut clients, iters)
+        })
+    });
+
+    g.finish()
+}
+
+type LargeMessage = [u64; 32];
+
+/// Compare runs with and without the `prefetch` feature.
+fn sequential_read(c: &mut Criterion) {
+    let mut g = c.benchmark_group("Sequential Read");
+    const QSIZE: usize = 1 << 16;
+
+    g.throughput(Throughput::Elements(QSIZE as u64));
+
+    let q = QueueClient::<LargeMessage>::new_queue(QSIZE);
+    for i in 0..QSIZE as u64 {
+        q.push([i; 32]);
+    }
+    let name = if cfg!(feature = "prefetch") {
+        "prefetch"
+    } else {
+        "no prefetch"
+    };
+    g.bench_function(name, |b| {
+        b.iter(|| {
+            // clones start at the oldest message
+            let mut r = q.clone();
+            while let Some(data) = r.next() {
+                black_box(data);
+            }
+        })
+    });
+
+    g.finish()
+}
+
+/// Compare runs with and without the `padded-cells` feature.
+fn adjacent_writers(c: &mut Criterion) {
+    let mut g = c.benchmark_group("Adjacent Writers");
+    const QSIZE: usize = 1024;
+    const WRITERS: usize = 4;
+
+    let name = if cfg!(feature = "padded-cells") {
+        "padded cells"
+    } else {
+        "unpadded cells"
+    };
+    g.bench_function(name, |b| {
+        b.iter_custom(|iters| {
+            let q = QueueClient::<u32>::new_queue(QSIZE);
+            let start = Instant::now();
+            thread::scope(|s| {
+                for _ in 0..WRITERS {
+                    s.spawn(|| {
+                        for i in 0..iters {
+                            q.push(i as u32);
+                        }
+                    });
+                }
+            });
+            start.elapsed()
+        })
+    });
+
+    g.finish()
+}
+
 criterion_group! {
     name = benches;
     config = Criterion::default();
-    targets = single_producer
+    targets = single_producer, adjacent_clients, sequential_read, adjacent_writers
 }
 criterion_main!(benches);
@@ -11,7 +11,9 @@
 //! To avoid blocking, writers are free to overwrite data that some or all
 //! readers have yet to consume. This means that readers are not guaranteed
 //! to see all writes. As such, this queue is unfit for anything resembling a
-//! task queue. Use `crossbeam-channel` or `bus` for that.
+//! task queue. Use `crossbeam-channel` or `bus` for that. With `std`,
+//! `BoundedQueueClient` trades speed for the opposite: writers wait for the
+//! slowest reader instead of overwriting what it has not read.
 //!
 //! Multi-consumer and broadcast means that only `Copy` types are supported.
 //!
@@ -19,10 +21,14 @@
 //! place in the buffer, each unit of data and index into the queue has an
 //! associated write "epoch". This data, along with a write-in-progress tag,
 //! is stored into an `AtomicUsize`. For this reason, allocations sizes are
-//! rounded up to a power of two. After around `2^(ptr width) - size`
-//! writes, information will overlap in the packed atomics, breaking the queue
-//! in unpredictable ways. `size` refers to the allocation size, not the
-//! user-requested size. Note that this happens before integer overflow.
+//! rounded up to a power of two. After around `2^(ptr width - 1)` writes, epochs
+//! would overlap the write-in-progress tag in the packed atomics. Instead, the
+//! queue is then poisoned: further pushes are dropped and
+//! `QueueClient::is_poisoned` returns true. Note that this happens before
+//! integer overflow. `QueueClient::writes_remaining` tells how close the
+//! queue is, and `QueueClient::push_checked` returns an error instead of
+//! dropping the push. `QueueClient::new_exact_queue` skips the rounding, at
+//! the cost of a division on every access.
 //!
 //! Writes are four step process. First, writers race for the next slot.
 //! The winning writer then initiates the write to the buffer slot with
@@ -42,7 +48,41 @@
 //! eliminated by filling the internal buffer with default data. However, this
 //! temporary data is never read and exists only to avoid `unsafe`.
 //!
-//! The only unsafe code is a `Sync` impl on the internal `Queue` type.
+//! Zero-sized element types work, but every slot holds the same value, so
+//! the only information left is how many pushes happened. Pushes still advance
+//! the write index and reads still validate epochs, so a queue of a marker
+//! type can be used to count events.
+//!
+//! `ConstQueue` holds its buffer in an array of a power of two size fixed at
+//! compile time, so it needs no allocation at all. It derefs to the same
+//! `Queue` API.
+//!
+//! The only unsafe code is a `Sync` impl on `Queue`, and the prefetch
+//! intrinsic behind the `prefetch` feature.
+//!
+//! # Features
+//!
+//! - `std` (default): everything that needs threads, locks or clocks, such as
+//!   the timeout reads, `push_rate` and `poll_next_waker`. Implies `alloc`.
+//! - `alloc`: heap allocated queues, `QueueClient` and the types built on
+//!   them. Without `std`, the crate is `no_std` and only needs `core` and
+//!   `alloc`. Without either, `ConstQueue` and `Cell` are still available.
+//! - `async`: `Consumer::into_stream`, a `futures_core::Stream` woken by
+//!   pushes. Implies `std`.
+//! - `log`: report consumer laps and writers long stuck behind a slow writer
+//!   of the same slot with `log::warn!`, and the approaching and reached
+//!   poison limit with `log::error!`.
+//! - `padded-cells`: align every slot of the buffer to a cache line, so
+//!   writers of neighbouring slots don't contend. This trades memory for
+//!   throughput, most of all for small messages.
+//! - `serde`: `Serialize` and `Deserialize` for `Queue`, to save and restore
+//!   a copy of its contents. Implies `alloc`.
+//! - `test-util`: `Queue::snapshot_slots`, which copies out the history with
+//!   holes marked, for comparing queues in tests. Implies `alloc`.
+//! - `prefetch`: on x86_64, `QueueClient::next` prefetches the data of the
+//!   following message. This can hide memory latency for large messages, but
+//!   hardware prefetchers often already cover reads in order, so compare the
+//!   `Sequential Read` benchmark with and without it.
 //!
 //! # Example
 //!
@@ -78,16 +118,129 @@
 //! thread.join().unwrap();
 //! ```
 
-use std::cell::Cell as ICell;
-use std::sync::atomic::{AtomicUsize, Ordering::*};
-use std::usize;
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(feature = "alloc")]
+use alloc::{boxed::Box, vec::Vec};
+use core::cell::Cell as ICell;
+use core::marker::PhantomData;
+use core::sync::atomic::Ordering::*;
+#[cfg(feature = "std")]
+use core::task::{Poll, Waker};
+#[cfg(feature = "std")]
+use std::sync::Mutex;
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+use sync::fence;
+#[cfg(feature = "alloc")]
+use sync::AtomicBool;
+use sync::AtomicUsize;
 // https://github.com/rust-lang/rfcs/blob/master/text/1443-extended-compare-and-swap.md
 
+/// The atomics the queue synchronizes with. Under `cfg(loom)` these are
+/// loom's model checked versions, so the tests in `loom_tests` can explore
+/// every interleaving of the epoch protocol.
+mod sync {
+    #[cfg(all(feature = "alloc", not(loom)))]
+    pub(crate) use core::sync::atomic::AtomicBool;
+    #[cfg(not(loom))]
+    pub(crate) use core::sync::atomic::{fence, AtomicUsize};
+    #[cfg(all(feature = "alloc", loom))]
+    pub(crate) use loom::sync::atomic::AtomicBool;
+    #[cfg(loom)]
+    pub(crate) use loom::sync::atomic::{fence, AtomicUsize};
+
+    /// Gives another thread a chance to run while we wait on it.
+    #[inline]
+    pub(crate) fn yield_now() {
+        #[cfg(loom)]
+        loom::thread::yield_now();
+        #[cfg(all(feature = "std", not(loom)))]
+        std::thread::yield_now();
+        #[cfg(not(any(feature = "std", loom)))]
+        core::hint::spin_loop();
+    }
+
+    /// Tells the CPU we are spinning, so it can give resources to a sibling
+    /// hyperthread. Loom needs a yield instead, or spinning never ends.
+    #[inline]
+    pub(crate) fn spin_loop() {
+        #[cfg(loom)]
+        loom::hint::spin_loop();
+        #[cfg(not(loom))]
+        core::hint::spin_loop();
+    }
+
+    /// Exponential backoff for the retry and wait loops.
+    #[derive(Debug, Default)]
+    pub(crate) struct Backoff {
+        step: u32,
+    }
+
+    impl Backoff {
+        /// After this many doublings, `snooze` yields instead of spinning
+        const SPIN_LIMIT: u32 = 6;
+
+        /// Backs off after losing a race, such as a failed CAS. Never yields,
+        /// since the winner has already made progress.
+        #[inline]
+        pub(crate) fn spin(&mut self) {
+            for _ in 0..1 << self.step.min(Self::SPIN_LIMIT) {
+                spin_loop();
+            }
+            if self.step <= Self::SPIN_LIMIT {
+                self.step += 1;
+            }
+        }
+
+        /// Backs off while waiting on another thread. Spins at first, then
+        /// yields once a short spin hasn't been enough.
+        #[inline]
+        pub(crate) fn snooze(&mut self) {
+            if self.step <= Self::SPIN_LIMIT {
+                for _ in 0..1 << self.step {
+                    spin_loop();
+                }
+                self.step += 1;
+            } else {
+                yield_now();
+            }
+        }
+    }
+}
+
+/// A single slot of the ring buffer, pairing data with the epoch of the write
+/// that produced it. `Queue` is built from these, and they can be used to
+/// build other ring layouts in the same way.
+///
 /// Write epochs: 0 represents defualt data, 1 is the first valid write
+///
+/// With the `padded-cells` feature, each cell is aligned like `CachePadded`,
+/// so writers of neighbouring slots don't contend on a cache line.
+#[cfg_attr(
+    all(
+        feature = "padded-cells",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    ),
+    repr(align(128))
+)]
+#[cfg_attr(
+    all(
+        feature = "padded-cells",
+        not(any(target_arch = "x86_64", target_arch = "aarch64"))
+    ),
+    repr(align(64))
+)]
 #[derive(Debug, Default)]
-struct Cell<T: Copy> {
+pub struct Cell<T: Copy> {
     data: ICell<T>,
     epoch: AtomicUsize,
+    /// The queue index the data was written for, checked by `Queue::read`
+    #[cfg(debug_assertions)]
+    seq: AtomicUsize,
 }
 
 #[cfg(target_pointer_width = "16")]
@@ -99,9 +252,82 @@ const SENTINEL_MASK: usize = 1 << 31;
 #[cfg(target_pointer_width = "64")]
 const SENTINEL_MASK: usize = 1 << 63;
 
+/// The write index at which the `log` feature reports the approaching poison limit
+#[cfg(feature = "log")]
+const WRITE_CEILING_WARNING: usize = SENTINEL_MASK / 4 * 3;
+
+/// How many times a writer backs off waiting for the previous writer of a
+/// cell before debug builds assume it will never finish.
+#[cfg(debug_assertions)]
+const WRITE_WAIT_LIMIT: usize = 1 << 20;
+
+/// How many times a writer backs off waiting for the previous writer of a
+/// cell before the `log` feature warns that it may be stuck. Below
+/// `WRITE_WAIT_LIMIT`, so debug builds warn before they panic.
+#[cfg(feature = "log")]
+const WRITE_WAIT_WARNING: usize = 1 << 19;
+
 impl<T: Copy> Cell<T> {
+    /// Writes `dat`, moving the cell from epoch `new_epoch - epoch_increment`
+    /// to `new_epoch`. Spins until the cell reaches the previous epoch, so
+    /// that writes to a cell happen in epoch order. `fence` decides how the
+    /// write is completed, see `WriterFence`.
+    ///
+    /// Debug builds panic if the previous writer does not finish after
+    /// `WRITE_WAIT_LIMIT` backoffs, rather than hang.
     #[inline]
-    pub fn write(&self, dat: T, new_epoch: usize, epoch_increment: usize) {
+    pub fn write(&self, dat: T, new_epoch: usize, epoch_increment: usize, fence: WriterFence) {
+        self.write_with(dat, new_epoch, epoch_increment, fence, |_| ())
+    }
+
+    /// Tries once to write `dat` like `write`, returning `false` if the cell
+    /// is not at epoch `new_epoch - epoch_increment`, such as when the
+    /// previous writer is still mid-write.
+    ///
+    /// On failure the cell is untouched: its data is not stored and the
+    /// write-in-progress bit is not set, so readers see no sign of the
+    /// attempt. The write of `new_epoch` is still owed, though. Writes to a
+    /// cell happen in epoch order, so the writer of the epoch after it waits
+    /// in `write` until this one completes. A caller which claimed the
+    /// epoch, as `Queue::push` does from its write pointer, must retry until
+    /// it succeeds.
+    #[inline]
+    pub fn try_write(
+        &self,
+        dat: T,
+        new_epoch: usize,
+        epoch_increment: usize,
+        fence: WriterFence,
+    ) -> bool {
+        if !self.try_begin_write(new_epoch, epoch_increment) {
+            return false;
+        }
+        self.data.set(dat);
+        self.finish_write(new_epoch, fence);
+        true
+    }
+
+    /// `write`, calling `during` on the cell after the data is stored but
+    /// before the write is confirmed.
+    #[inline]
+    fn write_with<F: FnOnce(&Self)>(
+        &self,
+        dat: T,
+        new_epoch: usize,
+        epoch_increment: usize,
+        fence: WriterFence,
+        during: F,
+    ) {
+        self.begin_write(new_epoch, epoch_increment);
+        self.data.set(dat);
+        during(self);
+        self.finish_write(new_epoch, fence);
+    }
+
+    /// The first half of `write`: marks the cell as being written at
+    /// `new_epoch`, once the write of the previous epoch has completed.
+    #[inline]
+    fn begin_write(&self, new_epoch: usize, epoch_increment: usize) {
         // little CAS loop to ensure exclusive, complete, sequential writes
         // downside: newer writes can't "kick" off old writers
         // though, a sufficiently large queue will ensure this basically never happens as long
@@ -109,78 +335,432 @@ impl<T: Copy> Cell<T> {
         // However, in the case of RT tasks starving non-RT tasks, this could happen
 
         let old_epoch = new_epoch - epoch_increment;
+        #[cfg(any(debug_assertions, feature = "log"))]
+        let mut waits = 0usize;
+        let mut backoff = sync::Backoff::default();
         loop {
-            // Could possibly downgrade seqcst to acqrel
+            // Acquire orders our write after the previous writer's, whose
+            // completing store we read. Release publishes our claim of the
+            // index, so a reader who sees this epoch also sees the write
+            // pointer past it when it catches up.
             match self.epoch.compare_exchange_weak(
                 old_epoch,
                 new_epoch | SENTINEL_MASK,
-                SeqCst,
+                AcqRel,
                 Acquire,
             ) {
-                Ok(_) => break,
+                Ok(_) => {
+                    // readers must see the sentinel before any of our data
+                    fence(Release);
+                    break;
+                }
                 // if any race occurs, there's a chance for a deadlock here
                 // ensure the epoch we are trying to advance from comes before us
                 // if not, we will be stuck in a loop forever and have big problems
-                Err(x) => debug_assert!(x & !SENTINEL_MASK <= old_epoch),
+                Err(x) => {
+                    debug_assert!(x & !SENTINEL_MASK <= old_epoch);
+                    if x != old_epoch {
+                        // the previous writer of this cell hasn't finished, give it a chance to run
+                        backoff.snooze();
+                        #[cfg(any(debug_assertions, feature = "log"))]
+                        {
+                            waits += 1;
+                        }
+                        // a preempted writer or a held `RangeToken` can take
+                        // this long legitimately, so only say so once
+                        #[cfg(feature = "log")]
+                        if waits == WRITE_WAIT_WARNING {
+                            log::warn!(
+                                "lfq writer of epoch {} still waiting for epoch {}, cell is at {} (write in progress: {})",
+                                new_epoch,
+                                old_epoch,
+                                x & !SENTINEL_MASK,
+                                x & SENTINEL_MASK != 0,
+                            );
+                        }
+                        #[cfg(debug_assertions)]
+                        assert!(
+                            waits < WRITE_WAIT_LIMIT,
+                            "writer of epoch {} gave up waiting for epoch {}, cell is at {} (write in progress: {})",
+                            new_epoch,
+                            old_epoch,
+                            x & !SENTINEL_MASK,
+                            x & SENTINEL_MASK != 0,
+                        );
+                    }
+                }
             }
         }
+    }
+
+    /// `begin_write`, but gives up rather than waiting if the cell is not at
+    /// the previous epoch. Orderings are as in `begin_write`.
+    #[inline]
+    fn try_begin_write(&self, new_epoch: usize, epoch_increment: usize) -> bool {
+        let old_epoch = new_epoch - epoch_increment;
+        // not weak, as a spurious failure would be reported to the caller
+        let won = self
+            .epoch
+            .compare_exchange(old_epoch, new_epoch | SENTINEL_MASK, AcqRel, Acquire)
+            .is_ok();
+        if won {
+            fence(Release);
+        }
+        won
+    }
+
+    /// The second half of `write`: completes the write begun by `begin_write`.
+    #[inline]
+    fn finish_write(&self, new_epoch: usize, fence: WriterFence) {
         // If a thread dies before storing the new_epoch value, then this slot can never be overwritten and will deadlock readers the entire queue
         // TODO: see if we can recover from this
-        self.data.set(dat);
         self.epoch.store(new_epoch, Release);
+        if fence == WriterFence::SeqCst {
+            // a SeqCst store alone doesn't order it before our later loads
+            sync::fence(SeqCst);
+        }
+    }
+
+    /// The epoch of the last write to start, without the write-in-progress tag.
+    #[inline]
+    pub fn current_epoch(&self) -> usize {
+        self.epoch.load(Acquire) & !SENTINEL_MASK
+    }
+
+    /// Whether a write to the cell has started but not completed.
+    #[inline]
+    pub fn is_writing(&self) -> bool {
+        self.epoch.load(Acquire) & SENTINEL_MASK != 0
     }
 
+    /// Reads the data without checking the epoch.
     #[inline]
     pub fn read(&self) -> T {
         self.data.get()
     }
+
+    /// Reads the data if the cell holds a completed write of `expected_epoch`
+    /// both before and after the read. Otherwise, returns the conflicting
+    /// epoch, which has the write-in-progress bit set if a write was underway.
+    #[inline]
+    pub fn read_validated(&self, expected_epoch: usize) -> Result<T, usize> {
+        self.read_validated_with(expected_epoch, Cell::read, || ())
+    }
+
+    /// `read_validated`, using `read` to read from the cell. Calls `raced`
+    /// if a write started during the read.
+    #[inline]
+    fn read_validated_with<R, F: FnOnce(&Self) -> R, G: FnOnce()>(
+        &self,
+        expected_epoch: usize,
+        read: F,
+        raced: G,
+    ) -> Result<R, usize> {
+        let epoch = self.epoch.load(Acquire);
+        if epoch != expected_epoch {
+            // if epochs don't match, it's over
+            return Err(epoch);
+        }
+        let rr = read(self);
+        // keep the read above from moving past the check below, which an
+        // acquire load alone allows
+        fence(Acquire);
+        // ensure that no writes occurred while we were reading
+        // a write would store a sentinel during the write if it
+        // didn't complete, and a new epoch if it did.
+        let epoch = self.epoch.load(Relaxed);
+        if epoch != expected_epoch {
+            raced();
+            return Err(epoch);
+        }
+        Ok(rr)
+    }
 }
 
+/// How a write is completed.
+///
+/// `Release` is all that readers of the queue itself need. Use `SeqCst` when
+/// a completed push must also be ordered against accesses to unrelated
+/// atomics, such as a writer which pushes and then checks a "reader is
+/// asleep" flag that the reader sets before checking the queue. `SeqCst`
+/// follows the completing store with a `fence(SeqCst)`. The reader must
+/// also put a `fence(SeqCst)` between setting its flag and checking the
+/// queue. Then at least one side sees the other's store. With `Release`, or
+/// without the reader's fence, both may miss each other's store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum WriterFence {
+    #[default]
+    Release,
+    SeqCst,
+}
+
+/// The buffer of cells behind a `Queue`. Implemented for the boxed slice
+/// allocated by `Queue::new` and the array held by `ConstQueue`.
+pub trait Storage<T: Copy>: AsRef<[Cell<T>]> {
+    /// The mask giving an index into the buffer, if known at compile time
+    const IDX_MASK: Option<usize>;
+}
+
+#[cfg(feature = "alloc")]
+impl<T: Copy> Storage<T> for Box<[Cell<T>]> {
+    const IDX_MASK: Option<usize> = None;
+}
+
+impl<T: Copy, const N: usize> Storage<T> for [Cell<T>; N] {
+    const IDX_MASK: Option<usize> = Some(N - 1);
+}
+
+/// The shared ring buffer behind every `QueueClient`. Holds no read
+/// position, so it offers pushes and reads by absolute index or of the
+/// latest write. Reach it through `QueueClient`'s `AsRef` impl.
 #[derive(Debug)]
-struct Queue<T: Copy> {
-    /// Heap array storing the actual slots
-    data: Box<[Cell<T>]>,
+pub struct Queue<
+    T: Copy,
+    #[cfg(feature = "alloc")] D = Box<[Cell<T>]>,
+    #[cfg(not(feature = "alloc"))] D,
+> {
+    /// Array storing the actual slots, on the heap unless this is a `ConstQueue`
+    data: D,
     /// An index representing an "virtual" "absolute" index, which is wrapped into an actual index in memory.
     /// Represents the next cell to be written, so a successful CAS loop uses the old value as the write index.
-    write_ptr: AtomicUsize,
-    /// A bitmask. write_ptr & idx_mask gives an index into `data`
-    idx_mask: usize,
+    /// Padded so that writers hammering it don't invalidate the line holding the read-only fields,
+    /// at the cost of making `Queue` a few cache lines large.
+    write_ptr: CachePadded<AtomicUsize>,
+    /// The length of `data`
+    size: usize,
+    /// The size asked for at construction, before any rounding up
+    requested: usize,
+    /// A bitmask. write_ptr & idx_mask gives an index into `data`.
+    /// `None` for exactly sized queues, which use `write_ptr % size` instead.
+    idx_mask: Option<usize>,
+    /// How `Cell::write` completes writes
+    fence: WriterFence,
+    /// Wakers registered by `QueueClient::poll_next_waker`, woken by the next push
+    #[cfg(feature = "std")]
+    wakers: Mutex<Vec<Waker>>,
+    /// Set while `wakers` may be non-empty, so pushes only take the lock when needed
+    #[cfg(feature = "std")]
+    waiting: AtomicBool,
+    /// Reads that raced with a write, see `read_race_count`.
+    /// Padded for the same reason as `write_ptr`, as racing readers bump it.
+    read_races: CachePadded<AtomicUsize>,
+    /// Live `QueueClient`s of this queue, see `consumer_count`
+    #[cfg(feature = "alloc")]
+    consumers: AtomicUsize,
+    /// Live `Producer`s and `BoundedProducer`s of this queue, see `producer_count`
+    #[cfg(feature = "alloc")]
+    producers: AtomicUsize,
+    /// The hook set by `set_on_evict`, and the registered read cursors that
+    /// it and bounded pushes are checked against
+    #[cfg(feature = "std")]
+    evictions: Evictions<T>,
+    _cells: PhantomData<Cell<T>>,
 }
 
+#[cfg(feature = "alloc")]
 impl<T: Default + Copy> Queue<T> {
-    pub fn new(size: usize) -> Self {
+    /// Allocates a buffer of `size` rounded up to a power of two, completing
+    /// writes with the given ordering.
+    pub fn new(size: usize, fence: WriterFence) -> Self {
+        assert!(size > 0);
+        let rounded = round_up_to_power_of_two(size);
+        let mut queue = Self::with_layout(rounded, Some(rounded - 1), fence);
+        queue.requested = size;
+        queue
+    }
+
+    /// Allocates a buffer of exactly `size`, completing writes with the given
+    /// ordering. Saves memory over `new` when `size` is not a power of two,
+    /// at the cost of a division on every access.
+    pub fn new_exact(size: usize, fence: WriterFence) -> Self {
         assert!(size > 0);
-        let size = round_up_to_power_of_two(size);
+        Self::with_layout(size, None, fence)
+    }
+
+    /// Allocates a buffer of `data.len()` rounded up to a power of two, and
+    /// pushes each element of `data` in order. Slots left over from the
+    /// rounding are never written, so they stay at epoch 0 and are not part
+    /// of the history. The first element is written at index `size()`, so
+    /// a client built with `QueueClient::from_parts` at that index replays
+    /// exactly `data`. An empty slice gives an empty queue of size 1.
+    pub fn from_slice(data: &[T], fence: WriterFence) -> Self {
+        let queue = Self::new(data.len().max(1), fence);
+        for &item in data {
+            queue.push(item);
+        }
+        queue
+    }
+
+    fn with_layout(size: usize, idx_mask: Option<usize>, fence: WriterFence) -> Self {
         let mut data = Vec::with_capacity(size);
         // the vec! macro requires a Clone bound
         for _ in 0..size {
             data.push(Default::default());
         }
-        let r = Self {
-            data: data.into_boxed_slice(),
-            write_ptr: AtomicUsize::new(size), // write epoch 1, idx 0
-            idx_mask: size - 1,
+        Self::from_storage(data.into_boxed_slice(), idx_mask, fence)
+    }
+}
+
+impl<T: Default + Copy, D: Storage<T>> Queue<T, D> {
+    /// Returns the queue to the state it was created in, without
+    /// reallocating. Every cell holds the default again at epoch 0 and the
+    /// write pointer starts over, so reads of old indices fail as they would
+    /// on a new queue.
+    pub fn clear(&mut self) {
+        for cell in self.data.as_ref() {
+            cell.data.set(T::default());
+            cell.epoch.store(0, Relaxed);
+            #[cfg(debug_assertions)]
+            cell.seq.store(0, Relaxed);
+        }
+        self.write_ptr.store(self.size(), Relaxed);
+        self.read_races.store(0, Relaxed);
+        #[cfg(feature = "std")]
+        {
+            // no client can be waiting while we hold the queue mutably
+            self.wakers
+                .get_mut()
+                .unwrap_or_else(|e| e.into_inner())
+                .clear();
+            self.waiting.store(false, Relaxed);
+        }
+    }
+
+    /// Claims `n` consecutive indices to write to at once, marking them all
+    /// as being written. The writes are completed together by
+    /// `RangeToken::commit`, or when the token is dropped. Until then,
+    /// writers which lap the queue wait on the claimed cells, so fill and
+    /// commit the range promptly.
+    ///
+    /// Panics if `n` is greater than the queue size.
+    pub fn reserve_range(&self, n: usize) -> RangeToken<'_, T, D> {
+        assert!(n <= self.size(), "range of {} is larger than the queue", n);
+        let start = self.claim(n);
+        let token = RangeToken {
+            queue: self,
+            start,
+            len: n,
         };
-        assert_eq!(r.idx_mask + 1, r.data.len());
-        assert_eq!(size, r.data.len());
-        r
+        for idx in token.indices() {
+            let cell = self.cell(idx);
+            cell.begin_write(self.epoch(idx), self.size());
+            // slots which are never set hold the default
+            cell.data.set(T::default());
+            #[cfg(debug_assertions)]
+            cell.seq.store(idx, Relaxed);
+        }
+        token
+    }
+}
+
+impl<T: Copy, D: Storage<T>> Queue<T, D> {
+    fn from_storage(data: D, idx_mask: Option<usize>, fence: WriterFence) -> Self {
+        let size = data.as_ref().len();
+        Self {
+            data,
+            write_ptr: CachePadded::new(AtomicUsize::new(size)), // write epoch 1, idx 0
+            size,
+            requested: size,
+            idx_mask,
+            fence,
+            #[cfg(feature = "std")]
+            wakers: Mutex::new(Vec::new()),
+            #[cfg(feature = "std")]
+            waiting: AtomicBool::new(false),
+            read_races: CachePadded::new(AtomicUsize::new(0)),
+            #[cfg(feature = "alloc")]
+            consumers: AtomicUsize::new(0),
+            #[cfg(feature = "alloc")]
+            producers: AtomicUsize::new(0),
+            #[cfg(feature = "std")]
+            evictions: Evictions::new(),
+            _cells: PhantomData,
+        }
     }
 }
 
+#[cfg(feature = "alloc")]
 impl<T: Copy> Queue<T> {
+    /// The largest power of two `size` whose buffer fits in `budget` bytes,
+    /// or 0 if not even one slot fits. Pass it to `Queue::new`.
+    ///
+    /// Debug builds store a sequence number in every cell to check reads,
+    /// so they fit fewer cells in the same budget than release builds.
+    pub fn capacity_for_bytes(budget: usize) -> usize {
+        let slots = budget / core::mem::size_of::<Cell<T>>();
+        match slots {
+            0 => 0,
+            _ => 1 << (usize::BITS - 1 - slots.leading_zeros()),
+        }
+    }
+
+    /// How many `QueueClient`s of this queue exist, including those inside
+    /// a `Consumer` or a bounded client. Producers are not counted, so a
+    /// producer can skip preparing messages nobody will read. Only a hint:
+    /// a client may be created or dropped right after this returns.
+    #[inline]
+    pub fn consumer_count(&self) -> usize {
+        self.consumers.load(Relaxed)
+    }
+
+    /// How many `Producer`s and `BoundedProducer`s of this queue exist.
+    /// Clients are not counted, even though they can push. Producers drop
+    /// with `Release` and this loads with `Acquire`, so once it returns 0,
+    /// every push made by a producer is visible to the caller.
+    #[inline]
+    pub fn producer_count(&self) -> usize {
+        self.producers.load(Acquire)
+    }
+
+    /// The size `Queue::new` and `QueueClient::new_queue` allocate when
+    /// asked for `requested`: the next power of two. For example, asking for
+    /// 100 gives 128. `new_exact` allocates `requested` as is.
+    pub const fn rounded_size(requested: usize) -> usize {
+        round_up_to_power_of_two(requested)
+    }
+}
+
+impl<T: Copy, D: Storage<T>> Queue<T, D> {
+    /// The size of the buffer. History is readable this far back.
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// How many messages the buffer holds, the same as `size`.
     #[inline]
-    fn size(&self) -> usize {
-        self.idx_mask + 1
+    pub fn capacity(&self) -> usize {
+        self.size
+    }
+
+    /// The size the queue was created with, before `new` rounded it up to
+    /// the allocated `capacity`. Equal to it for exact and const queues.
+    #[inline]
+    pub fn requested_capacity(&self) -> usize {
+        self.requested
     }
 
     #[inline]
     fn epoch(&self, idx: usize) -> usize {
-        idx & (!self.idx_mask)
+        match D::IDX_MASK.or(self.idx_mask) {
+            Some(mask) => idx & !mask,
+            None => idx - idx % self.size,
+        }
     }
 
     #[inline]
     fn modu(&self, idx: usize) -> usize {
-        idx & self.idx_mask
+        match D::IDX_MASK.or(self.idx_mask) {
+            Some(mask) => idx & mask,
+            None => idx % self.size,
+        }
+    }
+
+    /// The cell holding `idx`.
+    #[inline]
+    fn cell(&self, idx: usize) -> &Cell<T> {
+        &self.data.as_ref()[self.modu(idx)]
     }
 
     #[inline]
@@ -188,30 +768,264 @@ impl<T: Copy> Queue<T> {
         self.write_ptr.load(Acquire)
     }
 
+    /// The oldest index that can still hold a write, given the write pointer.
+    /// Indices below `size` only ever hold the default fill.
+    #[inline]
+    fn oldest_index(&self, write_ptr: usize) -> usize {
+        (write_ptr - self.size()).max(self.size())
+    }
+
     #[inline]
     pub fn push(&self, data: T) {
+        self.push_with(|| data)
+    }
+
+    /// Claims the next slot, and only then calls `make` to produce the data.
+    #[inline]
+    pub fn push_with<F: FnOnce() -> T>(&self, make: F) {
+        let old = self.claim(1);
+        self.write_claimed(old, make);
+        self.notify();
+    }
+
+    /// Pushes all of `items` as consecutive writes, advancing the write
+    /// pointer once for the whole batch instead of once per item.
+    ///
+    /// The claimed range belongs to this call alone, so pushes from other
+    /// writers land before or after the batch, never inside it. Nothing can
+    /// fail once the range is claimed: each slot waits for its previous
+    /// write like `push` does, and items claimed past the poison limit are
+    /// dropped like any other push. A batch longer than the queue overwrites
+    /// its own start.
+    pub fn push_batch(&self, items: &[T]) {
+        if items.is_empty() {
+            return;
+        }
+        let start = self.claim(items.len());
+        for (idx, &data) in (start..).zip(items) {
+            self.write_claimed(idx, || data);
+        }
+        self.notify();
+    }
+
+    /// Pushes `data` only if it can be written without waiting. If the
+    /// previous write to the next cell has not completed, returns `data`
+    /// back.
+    ///
+    /// The cell is checked before the write is claimed, so a failed attempt
+    /// leaves no hole in the sequence for readers or later writers to wait
+    /// on. Once claimed, the previous write of the cell has completed and
+    /// nobody else can claim it, so the write goes ahead without waiting.
+    pub fn try_push(&self, data: T) -> Result<(), T> {
+        self.try_push_below(data, usize::MAX)
+    }
+
+    /// `try_push`, also giving up if the next index is not below `limit`.
+    fn try_push_below(&self, data: T, limit: usize) -> Result<(), T> {
+        let ready = |idx: usize| {
+            // poisoned writes are dropped without touching a cell
+            idx < limit
+                && (idx & SENTINEL_MASK != 0
+                    || self.cell(idx).epoch.load(Acquire) == self.epoch(idx) - self.size())
+        };
+        match self.claim_if(1, ready) {
+            Some(old) => {
+                self.write_claimed(old, || data);
+                self.notify();
+                Ok(())
+            }
+            None => Err(data),
+        }
+    }
+
+    /// Pushes `data` unless the queue is out of writes, in which case the
+    /// push is refused rather than dropped, and the queue is not poisoned
+    /// by it. See `writes_remaining`.
+    pub fn push_checked(&self, data: T) -> Result<(), QueueExhausted> {
+        match self.claim_if(1, |idx| idx & SENTINEL_MASK == 0) {
+            Some(old) => {
+                self.write_claimed(old, || data);
+                self.notify();
+                Ok(())
+            }
+            None => Err(QueueExhausted),
+        }
+    }
+
+    /// Writes the result of `make` to the claimed index `idx`.
+    #[inline]
+    fn write_claimed<F: FnOnce() -> T>(&self, idx: usize, make: F) {
+        if idx & SENTINEL_MASK != 0 {
+            // the epoch would overlap the write-in-progress tag, so the queue is poisoned
+            return;
+        }
+        // now we can write our data into idx
+        self.cell(idx)
+            .write_with(make(), self.epoch(idx), self.size(), self.fence, |_cell| {
+                #[cfg(debug_assertions)]
+                _cell.seq.store(idx, Relaxed);
+            });
+    }
+
+    /// Advances the write pointer by `n`, returning the first claimed index.
+    #[inline]
+    fn claim(&self, n: usize) -> usize {
+        match self.claim_if(n, |_| true) {
+            Some(old) => old,
+            None => unreachable!(),
+        }
+    }
+
+    /// `claim`, giving up if `ready` returns false for the first index.
+    #[inline]
+    fn claim_if<F: Fn(usize) -> bool>(&self, n: usize, ready: F) -> Option<usize> {
         // CAS loop until we get our turn to write
         let mut old = self.write_ptr.load(Relaxed);
+        let mut backoff = sync::Backoff::default();
         loop {
-            let new = old + 1;
+            if !ready(old) {
+                return None;
+            }
+            let new = old + n;
+            // Exclusive ranges only need the RMW to be atomic. Readers are
+            // ordered against the claim by the release of the cell epoch CAS
+            // that follows it, and wakers by the fence in `notify`.
             match self
                 .write_ptr
-                .compare_exchange_weak(old, new, SeqCst, Relaxed) // Could maybe improve the success ordering
+                .compare_exchange_weak(old, new, AcqRel, Relaxed)
             {
                 Ok(_) => break,
-                Err(x) => old = x,
+                Err(x) => {
+                    old = x;
+                    backoff.spin();
+                }
+            }
+        }
+        #[cfg(feature = "log")]
+        {
+            let claimed = old..old + n;
+            if claimed.contains(&WRITE_CEILING_WARNING) {
+                log::error!(
+                    "lfq queue has used 3/4 of its {} writes and will be poisoned at the limit",
+                    SENTINEL_MASK
+                );
+            }
+            if claimed.contains(&SENTINEL_MASK) {
+                log::error!(
+                    "lfq queue is poisoned, epochs would overlap the write-in-progress tag"
+                );
+            }
+        }
+        #[cfg(feature = "std")]
+        if self.evictions.armed.load(Relaxed) {
+            self.evict(old, n);
+        }
+        Some(old)
+    }
+
+    /// Calls the `set_on_evict` hook for each message in the cells the
+    /// writes from `first` are about to overwrite, if the slowest
+    /// registered client has not read it yet.
+    #[cfg(feature = "std")]
+    #[cold]
+    fn evict(&self, first: usize, n: usize) {
+        // a lap back from our writes, skipping the default fill
+        let end = (first + n).saturating_sub(self.size);
+        let start = first.saturating_sub(self.size).max(self.size);
+        if start >= end {
+            return;
+        }
+        let start = match self.evictions.cursors.slowest() {
+            Some(slowest) => start.max(slowest),
+            None => return,
+        };
+        if start >= end {
+            return;
+        }
+        // cloned out so the hook can push without deadlocking
+        let hook = match &*self.evictions.lock() {
+            Some(hook) => hook.clone(),
+            None => return,
+        };
+        for idx in start..end {
+            // the write may still be in progress, or was overwritten already
+            if let Ok(data) = self.read(idx) {
+                hook(idx, data);
+            }
+        }
+    }
+
+    /// Calls `on_evict` with the index and value of every message a push
+    /// overwrites before the slowest client registered with
+    /// `QueueClient::register_cursor` has read it. Without registered
+    /// clients, it is never called. Replaces any previous hook.
+    ///
+    /// The hook runs on the pushing thread, after the push has claimed its
+    /// slot and before it writes there. It is best effort: a message whose
+    /// write was still in progress is not reported, and one read at the same
+    /// moment as the push may be.
+    ///
+    /// Once a hook is set, every push that overwrites a message takes a lock
+    /// to find the slowest registered client, and another to fetch the hook
+    /// when that client has not read the message. Pushes of a queue with a
+    /// hook are slower for it, even when nothing is evicted.
+    #[cfg(feature = "std")]
+    pub fn set_on_evict<F: Fn(usize, T) + Send + Sync + 'static>(&self, on_evict: F) {
+        *self.evictions.lock() = Some(Arc::new(on_evict));
+        self.evictions.armed.store(true, Relaxed);
+    }
+
+    /// Wakes any registered wakers after a write completes.
+    #[inline]
+    fn notify(&self) {
+        // pairs with the fence in `register_waker`, so either we see the
+        // waker or its owner sees our write
+        #[cfg(feature = "std")]
+        {
+            fence(SeqCst);
+            if self.waiting.load(Relaxed) {
+                self.wake_all();
             }
         }
-        // now we can write our data into old
-        self.data[self.modu(old)].write(data, self.epoch(old), self.size());
+    }
+
+    #[cfg(feature = "std")]
+    /// Registers `waker` to be woken by the next push.
+    fn register_waker(&self, waker: &Waker) {
+        let mut wakers = self.wakers.lock().unwrap_or_else(|e| e.into_inner());
+        if !wakers.iter().any(|w| w.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+        self.waiting.store(true, Relaxed);
+        drop(wakers);
+        fence(SeqCst);
+    }
+
+    #[cfg(feature = "std")]
+    #[cold]
+    fn wake_all(&self) {
+        let wakers = {
+            let mut wakers = self.wakers.lock().unwrap_or_else(|e| e.into_inner());
+            self.waiting.store(false, Relaxed);
+            core::mem::take(&mut *wakers)
+        };
+        for waker in wakers {
+            waker.wake();
+        }
     }
 
     /// Reads the last value that has a write initiated. Returns `None` if the write has not completed.
     /// Fails if nothing has been written to the queue.
     #[inline]
     pub fn try_read_latest(&self) -> Option<T> {
+        self.try_read_latest_indexed().map(|(_idx, data)| data)
+    }
+
+    /// The same as `try_read_latest`, but also returns the index read from.
+    #[inline]
+    pub fn try_read_latest_indexed(&self) -> Option<(usize, T)> {
         let idx = self.write_ptr.load(Acquire) - 1;
-        self.read(idx).ok()
+        self.read(idx).ok().map(|data| (idx, data))
     }
 
     /// Starts at the most recently initiated write, walking backwards until it finds a successful write.
@@ -220,11 +1034,21 @@ impl<T: Copy> Queue<T> {
     /// (imagine 5 writers on a queue of size 2). The same OOB read will occur.
     #[inline]
     pub fn read_latest(&self) -> T {
-        let mut idx = self.write_ptr.load(Acquire) - 1;
+        self.read_latest_indexed().1
+    }
+
+    /// The same as `read_latest`, but also returns the index read from.
+    #[inline]
+    pub fn read_latest_indexed(&self) -> (usize, T) {
+        let mut idx = self.latest_unpoisoned();
         loop {
             match self.read(idx) {
                 Ok(data) => {
-                    return data;
+                    return (idx, data);
+                }
+                // we were descheduled while walking back, and the writers lapped us
+                Err(epoch) if epoch & !SENTINEL_MASK > self.epoch(idx) => {
+                    idx = self.latest_unpoisoned();
                 }
                 Err(_epoch) => {
                     idx -= 1;
@@ -233,70 +1057,645 @@ impl<T: Copy> Queue<T> {
         }
     }
 
-    /// Busy waits for the most recently initiated write to complete. Will not chase new writes after inovacation.
+    /// Like `read_latest_indexed`, but only walks back through the readable
+    /// history, so it is safe on an empty queue. If no completed write is
+    /// found, starts again from the newest write until `timeout` elapses.
+    #[cfg(feature = "std")]
     #[inline]
-    pub fn read_latest_blocking(&self) -> T {
-        let idx = self.write_ptr.load(Acquire) - 1;
+    pub fn read_latest_timeout_indexed(&self, timeout: Duration) -> Option<(usize, T)> {
+        let start = Instant::now();
+        let mut backoff = sync::Backoff::default();
         loop {
-            match self.read(idx) {
-                Ok(data) => {
-                    return data;
-                }
-                Err(_epoch) => (),
+            if let Some(latest) = self.read_latest_in_history() {
+                return Some(latest);
             }
+            if start.elapsed() >= timeout {
+                return None;
+            }
+            backoff.snooze();
         }
     }
 
-    /// If the idx is still valid, returns Ok(T), else Err(epoch)
+    /// Walks back from the newest write through the readable history,
+    /// returning the first completed write.
+    #[cfg(feature = "alloc")]
     #[inline]
-    pub fn read(&self, idx: usize) -> Result<T, usize> {
-        let cell = &self.data[self.modu(idx)];
-        let epoch = cell.epoch.load(Acquire);
-        if epoch != self.epoch(idx) {
-            // if epochs don't match, it's over
-            return Err(epoch);
-        }
-        let rr = cell.read();
-        // ensure that no writes occurred while we were reading
-        // a write would store a sentinel during the write if it
-        // didn't complete, and a new epoch if it did.
-        let epoch = cell.epoch.load(Acquire);
-        if epoch != self.epoch(idx) {
-            return Err(epoch);
+    fn read_latest_in_history(&self) -> Option<(usize, T)> {
+        let oldest = self.oldest_index(self.next_write_ptr());
+        let mut idx = self.latest_unpoisoned() + 1;
+        while idx > oldest {
+            idx -= 1;
+            if let Ok(data) = self.read(idx) {
+                return Some((idx, data));
+            }
         }
-        Ok(rr)
+        None
     }
-}
 
-// The way Queue writes to Cell, the constraints used in `read`, guarantees no data races
-unsafe impl<T: Copy> Sync for Queue<T> {}
+    /// Reads the write `n` before the most recently initiated one, so
+    /// `read_back(0)` is the latest. Fails if that write is still in
+    /// progress, has been overwritten, or never happened.
+    #[inline]
+    pub fn read_back(&self, n: usize) -> Result<T, ReadError> {
+        let head = self.next_write_ptr();
+        let idx = match (head - 1).checked_sub(n) {
+            Some(idx) if idx >= self.size() => idx,
+            _ => return Err(ReadError::NotYetWritten),
+        };
+        if idx < head - self.size() {
+            return Err(ReadError::Lapped);
+        }
+        self.read(idx).map_err(|epoch| {
+            if epoch & !SENTINEL_MASK > self.epoch(idx) {
+                ReadError::Lapped
+            } else {
+                ReadError::InProgress
+            }
+        })
+    }
 
-use std::sync::Arc;
+    /// The oldest index still held in the history. Equal to
+    /// `newest_index() + 1` until the first push, as the first write is at
+    /// index `size()`.
+    #[inline]
+    pub fn oldest_valid_index(&self) -> usize {
+        self.oldest_index(self.next_write_ptr())
+    }
 
-/// A streaming reader and writer holding an`Arc` to a queue buffer.
-///
-/// Use `Clone::clone` to create another reader/writer to the same queue.
-/// The new client will start reading at original's read location at the time
-/// of the clone.
-#[derive(Debug, Clone)]
-pub struct QueueClient<T: Copy> {
-    queue: Arc<Queue<T>>,
-    to_read: usize,
-}
+    /// The index of the most recently initiated write, which may still be in
+    /// progress.
+    #[inline]
+    pub fn newest_index(&self) -> usize {
+        self.next_write_ptr() - 1
+    }
 
-impl<T: Default + Copy> QueueClient<T> {
-    /// Create a new queue and return a client to it. Allocates a buffer of
-    /// `size` rounded up to a power of two. The first element is the next
-    /// to be read.
-    pub fn new_queue(size: usize) -> Self {
-        let q = Queue::new(size);
-        let to_read = q.size();
-        Self {
-            queue: Arc::new(q),
-            to_read,
+    /// Fills `out` with up to `n` of the most recent completed writes, oldest
+    /// first, and returns how many it filled. Walks back from the newest
+    /// write like `read_latest`, passing over writes in progress, but never
+    /// further than the history, so fewer than `n` are returned if fewer
+    /// have been written. Also capped by the length of `out`.
+    pub fn read_latest_n(&self, n: usize, out: &mut [T]) -> usize {
+        let n = n.min(out.len()).min(self.size());
+        let oldest = self.oldest_valid_index();
+        let mut idx = self.next_write_ptr();
+        let mut filled = 0;
+        // fill from the back, so the newest write ends up last
+        while filled < n && idx > oldest {
+            idx -= 1;
+            if let Ok(data) = self.read(idx) {
+                filled += 1;
+                out[n - filled] = data;
+            }
         }
+        out.copy_within(n - filled..n, 0);
+        filled
+    }
+
+    /// Copies out the history, oldest first, pairing each index with its
+    /// data, or `None` where the write is incomplete or was overwritten
+    /// while copying. With no concurrent writers the result is
+    /// deterministic, so two queues can be compared with `==`.
+    #[cfg(feature = "test-util")]
+    pub fn snapshot_slots(&self) -> Vec<(usize, Option<T>)> {
+        (self.oldest_valid_index()..self.next_write_ptr())
+            .map(|idx| (idx, self.read(idx).ok()))
+            .collect()
+    }
+
+    /// Reads every index from `start` up to but excluding `end`, clamped to
+    /// the history as of this call. Each item is the same as `read(idx)`, so
+    /// writes which are in progress or overwritten while iterating come out
+    /// as `Err`. To replay the whole history, read from
+    /// `oldest_valid_index()` to `newest_index() + 1`.
+    #[inline]
+    pub fn read_range(
+        &self,
+        start: usize,
+        end: usize,
+    ) -> impl Iterator<Item = Result<T, usize>> + '_ {
+        let head = self.next_write_ptr();
+        (start.max(self.oldest_index(head))..end.min(head)).map(move |idx| self.read(idx))
+    }
+
+    /// Busy waits for the most recently initiated write to complete. Will not chase new writes after inovacation.
+    #[inline]
+    pub fn read_latest_blocking(&self) -> T {
+        let idx = self.latest_unpoisoned();
+        let mut backoff = sync::Backoff::default();
+        loop {
+            match self.read(idx) {
+                Ok(data) => {
+                    return data;
+                }
+                Err(_epoch) => backoff.snooze(),
+            }
+        }
+    }
+
+    /// The same as `read_latest_blocking`, but gives up and returns `None`
+    /// after retrying `spins` times, so a writer descheduled mid-write can't
+    /// hang the reader. Zero spins is the same as `try_read_latest`.
+    #[inline]
+    pub fn try_read_latest_blocking(&self, spins: usize) -> Option<T> {
+        let idx = self.latest_unpoisoned();
+        for _ in 0..spins {
+            match self.read(idx) {
+                Ok(data) => return Some(data),
+                Err(_epoch) => sync::spin_loop(),
+            }
+        }
+        self.read(idx).ok()
+    }
+
+    /// The same as `read_latest_blocking`, but gives up and returns `None`
+    /// once `timeout` has elapsed. Unlike `read_latest_timeout_indexed`,
+    /// this waits for the newest write rather than settling for an older
+    /// one that has completed.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn read_latest_timeout(&self, timeout: Duration) -> Option<T> {
+        let start = Instant::now();
+        let idx = self.latest_unpoisoned();
+        let mut backoff = sync::Backoff::default();
+        loop {
+            if let Ok(data) = self.read(idx) {
+                return Some(data);
+            }
+            if start.elapsed() >= timeout {
+                return None;
+            }
+            backoff.snooze();
+        }
+    }
+
+    /// Whether a write has been attempted at an index whose epoch overlaps the
+    /// write-in-progress tag. Such writes are dropped, and reads of those
+    /// indices fail as if the write was still in progress.
+    #[inline]
+    pub fn is_poisoned(&self) -> bool {
+        self.next_write_ptr() > SENTINEL_MASK
+    }
+
+    /// How many more pushes can be made before the queue is poisoned.
+    #[inline]
+    pub fn writes_remaining(&self) -> usize {
+        SENTINEL_MASK.saturating_sub(self.next_write_ptr())
+    }
+
+    /// The most recently initiated write, or the last index before poisoning.
+    #[inline]
+    fn latest_unpoisoned(&self) -> usize {
+        (self.next_write_ptr() - 1).min(SENTINEL_MASK - 1)
+    }
+
+    /// Moves the write pointer as if `write_ptr - size` writes had completed.
+    #[cfg(all(test, feature = "std", not(loom)))]
+    fn fast_forward(&self, write_ptr: usize) {
+        assert_eq!(self.modu(write_ptr), 0);
+        for cell in self.data.as_ref().iter() {
+            cell.epoch.store(write_ptr - self.size(), SeqCst);
+        }
+        self.write_ptr.store(write_ptr, SeqCst);
+    }
+
+    /// If the idx is still valid, returns Ok(T), else Err(epoch)
+    #[inline]
+    pub fn read(&self, idx: usize) -> Result<T, usize> {
+        if idx & SENTINEL_MASK != 0 {
+            return Err(SENTINEL_MASK);
+        }
+        let cell = self.cell(idx);
+        let count_race = || {
+            self.read_races.fetch_add(1, Relaxed);
+        };
+        #[cfg(debug_assertions)]
+        {
+            let (data, seq) = cell.read_validated_with(
+                self.epoch(idx),
+                |cell| (cell.read(), cell.seq.load(Relaxed)),
+                count_race,
+            )?;
+            // the default fill is never written, so has no sequence number
+            if idx >= self.size() {
+                assert_eq!(
+                    seq, idx,
+                    "read index {} from a cell written for index {}",
+                    idx, seq
+                );
+            }
+            Ok(data)
+        }
+        #[cfg(not(debug_assertions))]
+        cell.read_validated_with(self.epoch(idx), Cell::read, count_race)
+    }
+
+    /// `read`, pairing the data with its index. Indices are the sequence
+    /// numbers of pushes, in the order their slots were claimed.
+    #[inline]
+    pub fn read_with_seq(&self, idx: usize) -> Result<(usize, T), usize> {
+        self.read(idx).map(|data| (idx, data))
+    }
+
+    /// How many pushes have been started since the queue was created,
+    /// including any dropped after poisoning.
+    #[inline]
+    pub fn write_count(&self) -> usize {
+        self.next_write_ptr() - self.size()
+    }
+
+    /// How many reads have failed because a write to the same cell started
+    /// while they were reading. A high count means readers are too close to
+    /// the oldest data, and should catch up with a larger margin.
+    #[inline]
+    pub fn read_race_count(&self) -> usize {
+        self.read_races.load(Relaxed)
+    }
+
+    /// Zeroes the shared counters, currently `read_race_count`, e.g. at the
+    /// start of each reporting interval. Races counted by reads running at
+    /// the same time may land on either side of the reset, so under load the
+    /// new zero point is approximate.
+    #[inline]
+    pub fn reset_stats(&self) {
+        self.read_races.store(0, Relaxed);
+    }
+}
+
+impl<T: Copy, D: Storage<T>> Queue<T, D> {
+    /// Hints that the data at `idx` will be read soon.
+    #[cfg(feature = "prefetch")]
+    #[inline]
+    fn prefetch(&self, idx: usize) {
+        let _data = self.cell(idx).data.as_ptr();
+        #[cfg(target_arch = "x86_64")]
+        // SSE is part of the x86_64 baseline, and a prefetch never faults
+        unsafe {
+            use core::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+            _mm_prefetch::<_MM_HINT_T0>(_data as *const i8)
+        }
+    }
+}
+
+// The way Queue writes to Cell, the constraints used in `read`, guarantees no data races
+#[cfg(feature = "alloc")]
+unsafe impl<T: Copy> Sync for Queue<T> {}
+unsafe impl<T: Copy, const N: usize> Sync for Queue<T, [Cell<T>; N]> {}
+
+/// The serialized form of a `Queue`: its layout, the write pointer, and the
+/// epoch and data of every cell.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename = "Queue")]
+struct QueueRepr<T> {
+    size: usize,
+    /// Missing from copies saved before it was added
+    #[serde(default)]
+    requested: Option<usize>,
+    exact: bool,
+    fence: WriterFence,
+    write_ptr: usize,
+    cells: Vec<(usize, T)>,
+}
+
+/// Serializes a point-in-time copy of the queue, which deserializes to an
+/// equivalent queue: clients reading it see the same history at the same
+/// indices. Writes must not race with serialization. If a write is found in
+/// progress, serialization fails, but a push which completes during it can
+/// still leave the copy inconsistent.
+#[cfg(feature = "serde")]
+impl<T: Copy + serde::Serialize> serde::Serialize for Queue<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut cells = Vec::with_capacity(self.size());
+        for cell in self.data.iter() {
+            let epoch = cell.epoch.load(Acquire);
+            if epoch & SENTINEL_MASK != 0 {
+                return Err(serde::ser::Error::custom(
+                    "cannot serialize a queue with a write in progress",
+                ));
+            }
+            cells.push((epoch, cell.read()));
+        }
+        QueueRepr {
+            size: self.size(),
+            requested: Some(self.requested),
+            exact: self.idx_mask.is_none(),
+            fence: self.fence,
+            write_ptr: self.next_write_ptr(),
+            cells,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T: Copy + Default + serde::Deserialize<'de>> serde::Deserialize<'de> for Queue<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+        let repr = QueueRepr::<T>::deserialize(deserializer)?;
+        if repr.size == 0 || repr.cells.len() != repr.size {
+            return Err(D::Error::custom("cell count does not match the queue size"));
+        }
+        if !repr.exact && !repr.size.is_power_of_two() {
+            return Err(D::Error::custom("masked queue size is not a power of two"));
+        }
+        if repr.write_ptr < repr.size {
+            return Err(D::Error::custom("write pointer is before the first write"));
+        }
+        let requested = repr.requested.unwrap_or(repr.size);
+        if requested == 0 || requested > repr.size || (repr.exact && requested != repr.size) {
+            return Err(D::Error::custom(
+                "requested size does not fit the queue size",
+            ));
+        }
+        let mut queue = if repr.exact {
+            Queue::new_exact(repr.size, repr.fence)
+        } else {
+            Queue::new(repr.size, repr.fence)
+        };
+        queue.requested = requested;
+        for (i, (cell, (epoch, data))) in queue.data.iter().zip(repr.cells).enumerate() {
+            if epoch & SENTINEL_MASK != 0 {
+                return Err(D::Error::custom("cell has a write in progress"));
+            }
+            // 0 is a cell never written, otherwise the write to it must have
+            // been claimed before the write pointer was saved
+            if epoch != 0
+                && (queue.epoch(epoch) != epoch
+                    || epoch > queue.epoch(repr.write_ptr - 1)
+                    || epoch + i >= repr.write_ptr)
+            {
+                return Err(D::Error::custom(format_args!(
+                    "cell {} has epoch {}, which does not fit write pointer {}",
+                    i, epoch, repr.write_ptr
+                )));
+            }
+            cell.data.set(data);
+            cell.epoch.store(epoch, Relaxed);
+            // an epoch is the index of the write to the first cell of its lap
+            #[cfg(debug_assertions)]
+            cell.seq.store(epoch + i, Relaxed);
+            #[cfg(not(debug_assertions))]
+            let _ = i;
+        }
+        queue.write_ptr.store(repr.write_ptr, Relaxed);
+        Ok(queue)
+    }
+}
+
+/// A `Queue` whose buffer is an array of `N` cells, so it needs no
+/// allocation and the index mask is known at compile time. `N` must be a
+/// power of two. Derefs to the `Queue`.
+#[derive(Debug)]
+pub struct ConstQueue<T: Copy, const N: usize>(Queue<T, [Cell<T>; N]>);
+
+impl<T: Default + Copy, const N: usize> ConstQueue<T, N> {
+    const POWER_OF_TWO: () = assert!(
+        N.is_power_of_two(),
+        "ConstQueue size must be a power of two"
+    );
+
+    /// Creates a queue with the default `WriterFence`.
+    pub fn new() -> Self {
+        Self::new_with_fence(WriterFence::default())
+    }
+
+    /// Creates a queue completing writes with the given ordering.
+    pub fn new_with_fence(fence: WriterFence) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::POWER_OF_TWO;
+        let data = core::array::from_fn(|_| Cell::default());
+        ConstQueue(Queue::from_storage(data, Some(N - 1), fence))
+    }
+}
+
+impl<T: Default + Copy, const N: usize> Default for ConstQueue<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Copy, const N: usize> core::ops::Deref for ConstQueue<T, N> {
+    type Target = Queue<T, [Cell<T>; N]>;
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[cfg(feature = "alloc")]
+use alloc::sync::Arc;
+
+/// Unparks a thread waiting in `QueueClient::next_wait`.
+#[cfg(feature = "std")]
+struct ThreadWaker(std::thread::Thread);
+
+#[cfg(feature = "std")]
+impl std::task::Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    /// One waker per thread, so repeated waits don't register duplicates
+    static THREAD_WAKER: Waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+}
+
+#[cfg(feature = "std")]
+/// How many messages `QueueClient::drain_for` reads between checks of the clock
+const DRAIN_CLOCK_INTERVAL: usize = 64;
+
+#[cfg(feature = "alloc")]
+/// How many reads `QueueClient::next` makes between samples of the lag for
+/// `ConsumerMetrics::max_lag`, to keep loads of the write pointer rare
+const LAG_SAMPLE_INTERVAL: usize = 64;
+
+#[cfg(feature = "alloc")]
+/// Per-client counters behind `QueueClient::consumer_metrics`
+#[derive(Debug, Default, Clone, Copy)]
+struct ReadCounters {
+    reads: usize,
+    dropped: usize,
+    max_lag: usize,
+    reconnects: usize,
+}
+
+#[cfg(feature = "alloc")]
+/// A streaming reader and writer holding an`Arc` to a queue buffer.
+///
+/// Use `Clone::clone` to create another reader/writer to the same queue.
+/// The new client will start reading at the oldest message still in the
+/// history. Use `clone_keeping_position` to start at the original's read
+/// location instead.
+#[derive(Debug)]
+pub struct QueueClient<T: Copy> {
+    queue: Arc<Queue<T>>,
+    to_read: usize,
+    /// The latest index and time at the previous `push_rate` call
+    #[cfg(feature = "std")]
+    rate_sample: Option<(usize, Instant)>,
+    /// The latest index at the previous `observe` call
+    observed: usize,
+    /// See `consumer_metrics`
+    counters: ReadCounters,
+    /// Our read position as published to `Queue::set_on_evict`, if registered
+    #[cfg(feature = "std")]
+    cursor: Option<Arc<AtomicUsize>>,
+}
+
+#[cfg(feature = "alloc")]
+impl<T: Default + Copy> QueueClient<T> {
+    /// Create a new queue and return a client to it. Allocates a buffer of
+    /// `size` rounded up to a power of two, see `Queue::rounded_size`.
+    ///
+    /// The client starts at the first write, which is also the next one: no
+    /// default-filled cell is ever read, and `next` returns `None` until
+    /// something is pushed. Clones made before any push start there too.
+    pub fn new_queue(size: usize) -> Self {
+        Self::new_queue_with_fence(size, WriterFence::default())
+    }
+
+    /// The same as `new_queue()`, but writes are completed with the given
+    /// ordering. Read the `WriterFence` documentation before use.
+    pub fn new_queue_with_fence(size: usize, fence: WriterFence) -> Self {
+        Self::from_queue(Queue::new(size, fence))
+    }
+
+    /// The same as `new_queue()`, but the buffer is exactly `size` long.
+    /// Indexing it takes a division rather than a mask, so prefer
+    /// `new_queue` unless the memory saved matters.
+    pub fn new_exact_queue(size: usize) -> Self {
+        Self::from_queue(Queue::new_exact(size, WriterFence::default()))
+    }
+
+    fn from_queue(q: Queue<T>) -> Self {
+        let to_read = q.size();
+        Self::with_position(Arc::new(q), to_read, to_read - 1)
+    }
+
+    /// Create a new queue of `size` rounded up to a power of two, and push
+    /// every item of `iter` onto it. If there are more items than fit, only
+    /// the newest are kept. The first element is the oldest one kept.
+    pub fn from_iter_sized<I: IntoIterator<Item = T>>(iter: I, size: usize) -> Self {
+        let mut client = Self::new_queue(size);
+        for data in iter {
+            client.push(data);
+        }
+        client.to_read = client.queue.oldest_index(client.queue.next_write_ptr());
+        client
+    }
+
+    /// Start configuring a new queue. See `QueueBuilder`.
+    pub fn builder() -> QueueBuilder<T> {
+        QueueBuilder::default()
+    }
+}
+
+#[cfg(feature = "alloc")]
+/// Configures a new queue, for when `new_queue` and its variants don't
+/// cover the options needed. Created by `QueueClient::builder`.
+///
+/// ```
+/// use lfq::{QueueClient, WriterFence};
+///
+/// let builder = QueueClient::<u32>::builder()
+///     .size(100)
+///     .fence(WriterFence::SeqCst);
+/// assert_eq!(builder.allocated_size(), Ok(128));
+/// let client = builder.build().unwrap();
+/// assert_eq!(client.size(), 128);
+/// ```
+#[derive(Debug)]
+pub struct QueueBuilder<T> {
+    size: usize,
+    exact: bool,
+    fence: WriterFence,
+    _queue: PhantomData<fn() -> T>,
+}
+
+#[cfg(feature = "alloc")]
+impl<T> Default for QueueBuilder<T> {
+    /// No size is set, so `size` must be called before `build`.
+    fn default() -> Self {
+        QueueBuilder {
+            size: 0,
+            exact: false,
+            fence: WriterFence::default(),
+            _queue: PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T> Clone for QueueBuilder<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T> Copy for QueueBuilder<T> {}
+
+#[cfg(feature = "alloc")]
+impl<T: Default + Copy> QueueBuilder<T> {
+    /// The number of messages to hold. Rounded up to a power of two unless
+    /// `exact` is set.
+    pub fn size(mut self, size: usize) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// Allocate exactly `size`, rather than rounding it up. See
+    /// `QueueClient::new_exact_queue`.
+    pub fn exact(mut self, exact: bool) -> Self {
+        self.exact = exact;
+        self
+    }
+
+    /// How writes are completed. Read the `WriterFence` documentation before
+    /// use.
+    pub fn fence(mut self, fence: WriterFence) -> Self {
+        self.fence = fence;
+        self
+    }
+
+    /// The size `build` will allocate, or an error if it would fail.
+    pub fn allocated_size(&self) -> Result<usize, InvalidSize> {
+        match self.size {
+            0 => Err(InvalidSize),
+            size if self.exact => Ok(size),
+            size => size.checked_next_power_of_two().ok_or(InvalidSize),
+        }
+    }
+
+    /// Allocates the queue and returns a client to it, which starts at the
+    /// next write like `new_queue`.
+    pub fn build(self) -> Result<QueueClient<T>, InvalidSize> {
+        let size = self.allocated_size()?;
+        Ok(QueueClient::from_queue(if self.exact {
+            Queue::new_exact(size, self.fence)
+        } else {
+            Queue::new(size, self.fence)
+        }))
+    }
+}
+
+/// The error returned by `QueueBuilder` when the size is zero, or too large
+/// to round up to a power of two.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidSize;
+
+impl core::fmt::Display for InvalidSize {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("queue size is zero or overflows when rounded up")
     }
 }
+
+#[cfg(feature = "std")]
+impl std::error::Error for InvalidSize {}
+
+#[cfg(feature = "alloc")]
 impl<T: Copy> QueueClient<T> {
     /// Resets the read stream to a valid message with a margin for writes
     /// "from behind" before the next read. This usually should not be used;
@@ -304,6 +1703,33 @@ impl<T: Copy> QueueClient<T> {
     #[inline]
     pub fn catch_up(&mut self, margin: usize) {
         self.to_read = self.queue.next_write_ptr() - self.queue.size() + margin;
+        self.publish();
+    }
+
+    /// Moves the read stream to the absolute `index`, clamped to the readable
+    /// history and the next write. Returns the index actually used.
+    #[inline]
+    pub fn catch_up_to(&mut self, index: usize) -> usize {
+        let head = self.queue.next_write_ptr();
+        self.to_read = index.max(self.queue.oldest_index(head)).min(head);
+        self.publish();
+        self.to_read
+    }
+
+    /// Moves the read stream past every write made so far, so the next
+    /// message read is the next one pushed.
+    #[inline]
+    pub fn catch_up_latest(&mut self) {
+        self.to_read = self.queue.next_write_ptr();
+        self.publish();
+    }
+
+    /// Moves the read stream to the oldest message still in the history,
+    /// or the first write if fewer than `size()` have been made.
+    #[inline]
+    pub fn catch_up_to_oldest(&mut self) {
+        self.to_read = self.queue.oldest_index(self.queue.next_write_ptr());
+        self.publish();
     }
 
     /// Resets the read stream to the most recently written data. This guarantees
@@ -311,6 +1737,28 @@ impl<T: Copy> QueueClient<T> {
     #[inline]
     pub fn reset(&mut self) {
         self.to_read = self.queue.next_write_ptr() - 1;
+        self.publish();
+    }
+
+    /// Clears the queue as `Queue::clear` does, and moves the read stream back
+    /// to the first write. Only possible while no other client shares the
+    /// queue: otherwise, returns false and does nothing.
+    pub fn clear(&mut self) -> bool
+    where
+        T: Default,
+    {
+        match Arc::get_mut(&mut self.queue) {
+            Some(queue) => queue.clear(),
+            None => return false,
+        }
+        self.to_read = self.queue.size();
+        self.observed = self.to_read - 1;
+        #[cfg(feature = "std")]
+        {
+            self.rate_sample = None;
+        }
+        self.publish();
+        true
     }
 
     /// Advances the read pointer `n` elements, faster than calling
@@ -318,6 +1766,13 @@ impl<T: Copy> QueueClient<T> {
     #[inline]
     pub fn skip(&mut self, n: usize) {
         self.to_read += n;
+        self.publish();
+    }
+
+    /// The ordering used to complete writes to the queue.
+    #[inline]
+    pub fn writer_fence(&self) -> WriterFence {
+        self.queue.fence
     }
 
     /// The size of the internal buffer. History is readable this far back.
@@ -326,12 +1781,80 @@ impl<T: Copy> QueueClient<T> {
         self.queue.size()
     }
 
+    /// See `Queue::capacity`.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+
+    /// See `Queue::requested_capacity`.
+    #[inline]
+    pub fn requested_capacity(&self) -> usize {
+        self.queue.requested_capacity()
+    }
+
+    /// The number of unread messages still in the history. If the writers
+    /// have lapped us, only the messages that can still be read are counted,
+    /// so this is at most `size()`. This is a snapshot, and may be stale as
+    /// soon as it returns.
+    #[inline]
+    pub fn len(&self) -> usize {
+        let head = self.queue.next_write_ptr();
+        head.saturating_sub(self.to_read.max(self.queue.oldest_index(head)))
+    }
+
+    /// Whether there are no unread messages. Has the same caveats as `len`.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     /// Push an element onto the end of the queue.
     #[inline]
     pub fn push(&self, data: T) {
         self.queue.push(data)
     }
 
+    /// Push an element onto the end of the queue, or return an error if the
+    /// queue is out of writes. See `Queue::push_checked`.
+    #[inline]
+    pub fn push_checked(&self, data: T) -> Result<(), QueueExhausted> {
+        self.queue.push_checked(data)
+    }
+
+    /// Push an element onto the end of the queue unless that would wait on
+    /// an incomplete write. See `Queue::try_push`.
+    #[inline]
+    pub fn try_push(&self, data: T) -> Result<(), T> {
+        self.queue.try_push(data)
+    }
+
+    /// Push an element built by `make` onto the end of the queue. `make` is
+    /// called after a slot has been claimed, which defers building large
+    /// elements until they are needed. The element is still copied into the
+    /// queue afterwards.
+    #[inline]
+    pub fn push_with<F: FnOnce() -> T>(&self, make: F) {
+        self.queue.push_with(make)
+    }
+
+    /// Push all of `items` onto the end of the queue as one contiguous run.
+    /// See `Queue::push_batch`.
+    #[inline]
+    pub fn push_batch(&self, items: &[T]) {
+        self.queue.push_batch(items)
+    }
+
+    /// Claim `n` consecutive writes to fill and publish together. See
+    /// `Queue::reserve_range`.
+    #[inline]
+    pub fn reserve_range(&self, n: usize) -> RangeToken<'_, T>
+    where
+        T: Default,
+    {
+        self.queue.reserve_range(n)
+    }
+
     /// Get the next message if it is still in the history.
     /// If not, the read pointer is reset to the oldest valid data, skipping
     /// dropped messages.
@@ -343,6 +1866,7 @@ impl<T: Copy> QueueClient<T> {
     ///
     /// Returns `None` if the next message has not been written or is currently being written.
     #[inline]
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Option<(T, usize)> {
         let old_to_read = self.to_read;
         // "backoff" our catch up in case writes are really fast
@@ -352,7 +1876,16 @@ impl<T: Copy> QueueClient<T> {
             match self.queue.read(self.to_read) {
                 Ok(data) => {
                     self.to_read += 1;
-                    return Some((data, self.to_read - old_to_read - 1));
+                    self.publish();
+                    #[cfg(feature = "prefetch")]
+                    self.queue.prefetch(self.to_read);
+                    let dropped = self.to_read - old_to_read - 1;
+                    #[cfg(feature = "log")]
+                    if dropped > 0 {
+                        log::warn!("lfq client lapped by writers, skipped {} messages", dropped);
+                    }
+                    self.count_read(dropped);
+                    return Some((data, dropped));
                 }
                 Err(epoch) => {
                     // first handle the case of a write
@@ -375,301 +1908,3928 @@ impl<T: Copy> QueueClient<T> {
         None
     }
 
-    /// The same as `next()`, but busy-waits until there is a newly written
-    /// message to read if we have read all of them.
-    #[inline]
-    pub fn next_blocking(&mut self) -> (T, usize) {
-        loop {
-            if let Some(data) = self.next() {
-                return data;
+    /// Fills `out` with consecutive messages and returns how many were read.
+    /// The first message is read as by `next()`, catching up if we were
+    /// lapped. After that, stops at the first message that is not ready
+    /// yet, or that was overwritten, so every message written to `out` is
+    /// valid and in sequence. Skipped messages are not reported.
+    pub fn next_n(&mut self, out: &mut [T]) -> usize {
+        let (first, rest) = match out.split_first_mut() {
+            Some(split) => split,
+            None => return 0,
+        };
+        match self.next() {
+            Some((data, _)) => *first = data,
+            None => return 0,
+        }
+        let mut count = 1;
+        for slot in rest {
+            match self.queue.read(self.to_read) {
+                Ok(data) => {
+                    *slot = data;
+                    self.to_read += 1;
+                    self.count_read(0);
+                    count += 1;
+                }
+                // an overtake is left for the next call to catch up from
+                Err(_) => break,
             }
         }
+        self.publish();
+        count
     }
 
-    /// Reads the latest complete write to the queue.
-    ///
-    /// It is possible for a writer to be pre-empted before the write is
-    /// completed. In this case, this method walks backwards from the write
-    /// pointer until it finds the latest completed write. As such, this will
-    /// read OOB if no data has been written yet.
+    /// The same as `next()`, but pairs the message with its absolute index
+    /// instead of the number skipped. Indices increase with the global push
+    /// order, so they can deduplicate messages across clients. A jump of more
+    /// than one between reads means the messages in between were skipped.
     #[inline]
-    pub fn latest(&self) -> T {
-        self.queue.read_latest()
+    pub fn next_with_seq(&mut self) -> Option<(usize, T)> {
+        self.next().map(|(data, _)| (self.to_read - 1, data))
     }
 
-    /// Selects the most recently earned write (ie: a thread has earned the
-    /// slot, but not necessarily completed the write) and then waits for
-    /// its completion. Use of `latest` is recommended over this method.
+    /// Reads the message `next()` would return, without moving the read
+    /// pointer. Returns `None` if it has not been written, is being written,
+    /// or has been overwritten. In the last case the following `next()`
+    /// catches up as usual, so it may return a different message than
+    /// `peek` saw.
     #[inline]
-    pub fn latest_write(&self) -> T {
-        self.queue.read_latest_blocking()
+    pub fn peek(&self) -> Option<T> {
+        self.queue.read(self.to_read).ok()
     }
 
-    /// Selects the most recently earned write (ie: a thread has earned the
-    /// slot, but not necessarily completed the write) and returns `None` if
-    /// the write has not yet completed.
-    #[inline]
-    pub fn try_latest_write(&self) -> Option<T> {
-        self.queue.try_read_latest()
+    /// The same as `next()`, but tells apart having read every message from
+    /// waiting on a write that has been claimed but not completed. The
+    /// caller may want to spin on `HeadInProgress`, but sleep on `CaughtUp`.
+    pub fn next_status(&mut self) -> NextStatus<T> {
+        match self.next() {
+            Some((data, _)) => NextStatus::Got(data),
+            None if self.to_read < self.queue.next_write_ptr() => NextStatus::HeadInProgress,
+            None => NextStatus::CaughtUp,
+        }
+    }
+
+    /// The same as `next()`, but never skips messages. If the writers have
+    /// lapped us, returns the missed range as a `Gap` without moving the read
+    /// pointer. Call `catch_up_to(gap.to)` to resume after the gap.
+    pub fn next_strict(&mut self) -> Result<Option<T>, Gap> {
+        match self.queue.read(self.to_read) {
+            Ok(data) => {
+                self.to_read += 1;
+                self.publish();
+                self.count_read(0);
+                Ok(Some(data))
+            }
+            Err(epoch) => {
+                let write_in_progress = epoch & SENTINEL_MASK > 0;
+                let epoch = epoch & !SENTINEL_MASK;
+                if epoch <= self.queue.epoch(self.to_read) || write_in_progress {
+                    Ok(None)
+                } else {
+                    Err(Gap {
+                        from: self.to_read,
+                        to: self.queue.oldest_index(self.queue.next_write_ptr()),
+                    })
+                }
+            }
+        }
+    }
+
+    /// The same as `next()`, but reports being lapped instead of skipping
+    /// silently. On `Overrun`, the read pointer has already caught up as
+    /// `next()` would, so the following call reads on from there.
+    /// `Ok(None)` means we are caught up or the next write is in progress.
+    pub fn try_next(&mut self) -> Result<Option<T>, Overrun> {
+        self.next_strict().map_err(|gap| {
+            self.catch_up(1);
+            let skipped = self.to_read - gap.from;
+            self.count_lap(skipped, self.lag() + skipped);
+            Overrun { skipped }
+        })
+    }
+
+    /// Updates the counters after any read of a message, having skipped
+    /// `dropped` before it.
+    #[inline]
+    // `usize::is_multiple_of` is newer than the toolchains we support
+    #[allow(clippy::manual_is_multiple_of)]
+    fn count_read(&mut self, dropped: usize) {
+        self.counters.reads += 1;
+        if dropped > 0 {
+            // we were also behind by the skipped messages and this one
+            self.count_lap(dropped, self.lag() + dropped + 1);
+        } else if self.counters.reads % LAG_SAMPLE_INTERVAL == 0 {
+            self.counters.max_lag = self.counters.max_lag.max(self.lag());
+        }
+    }
+
+    #[cold]
+    fn count_lap(&mut self, skipped: usize, lag_before: usize) {
+        self.counters.dropped += skipped;
+        self.counters.reconnects += 1;
+        self.counters.max_lag = self.counters.max_lag.max(lag_before);
+    }
+
+    /// This client's read position, lag and lap counters in one call. The
+    /// counters start at zero for each client, including clones.
+    pub fn consumer_metrics(&self) -> ConsumerMetrics {
+        let lag = self.lag();
+        ConsumerMetrics {
+            position: self.to_read,
+            lag,
+            dropped: self.counters.dropped,
+            max_lag: self.counters.max_lag.max(lag),
+            reconnects: self.counters.reconnects,
+        }
+    }
+
+    /// Zeroes the `dropped`, `max_lag` and `reconnects` counters of
+    /// `consumer_metrics`, e.g. at the start of each reporting interval.
+    /// The position and lag are unchanged. The queue's shared counters are
+    /// reset by `Queue::reset_stats`.
+    pub fn reset_stats(&mut self) {
+        self.counters = ReadCounters::default();
+    }
+
+    /// The same as `next()`, but busy-waits until there is a newly written
+    /// message to read if we have read all of them.
+    #[inline]
+    pub fn next_blocking(&mut self) -> (T, usize) {
+        let mut backoff = sync::Backoff::default();
+        loop {
+            if let Some(data) = self.next() {
+                return data;
+            }
+            backoff.snooze();
+        }
+    }
+
+    /// Returns `Ready` with the next message if there is one. Otherwise
+    /// registers `waker` to be woken by the next push and returns `Pending`.
+    /// This is the building block for driving a client from an async
+    /// executor. Pushes only take a lock while a waker is registered.
+    #[cfg(feature = "std")]
+    pub fn poll_next_waker(&mut self, waker: &Waker) -> Poll<T> {
+        if let Some((data, _)) = self.next() {
+            return Poll::Ready(data);
+        }
+        self.queue.register_waker(waker);
+        // a push may have landed before the waker was registered
+        match self.next() {
+            Some((data, _)) => Poll::Ready(data),
+            None => Poll::Pending,
+        }
+    }
+
+    /// The same as `next_blocking()`, but parks the thread until the next
+    /// push instead of spinning.
+    #[cfg(feature = "std")]
+    pub fn next_wait(&mut self) -> T {
+        THREAD_WAKER.with(|waker| loop {
+            if let Poll::Ready(data) = self.poll_next_waker(waker) {
+                return data;
+            }
+            std::thread::park();
+        })
+    }
+
+    /// The same as `next_wait()`, but gives up and returns `None` once
+    /// `timeout` has elapsed.
+    #[cfg(feature = "std")]
+    pub fn next_wait_timeout(&mut self, timeout: Duration) -> Option<T> {
+        let deadline = Instant::now() + timeout;
+        THREAD_WAKER.with(|waker| loop {
+            if let Poll::Ready(data) = self.poll_next_waker(waker) {
+                return Some(data);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return None;
+            }
+            std::thread::park_timeout(deadline - now);
+        })
+    }
+
+    /// Busy-waits for the next message like `next_blocking()`, but gives up
+    /// and returns `None` once `cancel` is set. The flag is checked between
+    /// attempts to read. Messages dropped to laps are not reported.
+    #[inline]
+    pub fn next_blocking_cancellable(&mut self, cancel: &AtomicBool) -> Option<T> {
+        let mut backoff = sync::Backoff::default();
+        loop {
+            if let Some((data, _)) = self.next() {
+                return Some(data);
+            }
+            if cancel.load(Relaxed) {
+                return None;
+            }
+            backoff.snooze();
+        }
+    }
+
+    /// Reads up to `max` messages into `buf`, stopping early once there is
+    /// nothing left to read or `budget` has elapsed. To keep the overhead
+    /// down, the clock is only checked every 64 messages. Returns how many
+    /// messages were read.
+    #[cfg(feature = "std")]
+    pub fn drain_for(&mut self, max: usize, budget: Duration, buf: &mut Vec<T>) -> usize {
+        let start = Instant::now();
+        let mut count = 0;
+        while count < max {
+            if count % DRAIN_CLOCK_INTERVAL == 0 && start.elapsed() >= budget {
+                break;
+            }
+            match self.next() {
+                Some((data, _)) => buf.push(data),
+                None => break,
+            }
+            count += 1;
+        }
+        count
+    }
+
+    /// Reads up to `N` messages into an array on the stack, stopping at the
+    /// first unavailable one. Returns the array and how many were read;
+    /// those are the leading `Some` elements.
+    pub fn poll_array<const N: usize>(&mut self) -> ([Option<T>; N], usize) {
+        let mut out = [None; N];
+        let mut count = 0;
+        for slot in out.iter_mut() {
+            match self.next() {
+                Some((data, _)) => *slot = Some(data),
+                None => break,
+            }
+            count += 1;
+        }
+        (out, count)
+    }
+
+    /// Alias of `push`, following channel naming.
+    #[inline]
+    pub fn send(&self, data: T) {
+        self.push(data)
+    }
+
+    /// `next` without the dropped count, following channel naming. Unlike a
+    /// channel, messages can be lost if writers overtake this client.
+    #[inline]
+    pub fn recv(&mut self) -> Option<T> {
+        QueueClient::next(self).first()
+    }
+
+    /// `next_blocking` without the dropped count, following channel naming.
+    #[inline]
+    pub fn recv_blocking(&mut self) -> T {
+        self.next_blocking().0
+    }
+
+    /// Reads the latest complete write to the queue.
+    ///
+    /// It is possible for a writer to be pre-empted before the write is
+    /// completed. In this case, this method walks backwards from the write
+    /// pointer until it finds the latest completed write. As such, this will
+    /// read OOB if no data has been written yet.
+    #[inline]
+    pub fn latest(&self) -> T {
+        self.queue.read_latest()
+    }
+
+    /// Selects the most recently earned write (ie: a thread has earned the
+    /// slot, but not necessarily completed the write) and then waits for
+    /// its completion. Use of `latest` is recommended over this method.
+    #[inline]
+    pub fn latest_write(&self) -> T {
+        self.queue.read_latest_blocking()
+    }
+
+    /// Selects the most recently earned write (ie: a thread has earned the
+    /// slot, but not necessarily completed the write) and returns `None` if
+    /// the write has not yet completed.
+    #[inline]
+    pub fn try_latest_write(&self) -> Option<T> {
+        self.queue.try_read_latest()
+    }
+
+    /// The same as `latest_write`, but waits at most `spins` retries for the
+    /// write to complete. See `Queue::try_read_latest_blocking`.
+    #[inline]
+    pub fn try_latest_write_blocking(&self, spins: usize) -> Option<T> {
+        self.queue.try_read_latest_blocking(spins)
+    }
+
+    /// The same as `latest_write`, but waits at most `timeout` for the write
+    /// to complete. See `Queue::read_latest_timeout`.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn latest_write_timeout(&self, timeout: Duration) -> Option<T> {
+        self.queue.read_latest_timeout(timeout)
+    }
+
+    /// Busy-waits until the most recently earned write has completed with a
+    /// value other than `from`, then returns it. Pushes of `from` itself do
+    /// not end the wait.
+    pub fn wait_until_changed(&self, from: &T) -> T
+    where
+        T: PartialEq,
+    {
+        let mut backoff = sync::Backoff::default();
+        loop {
+            match self.queue.try_read_latest() {
+                Some(data) if data != *from => return data,
+                _ => backoff.snooze(),
+            }
+        }
+    }
+
+    /// The same as `try_latest_write`, but also returns the index of the
+    /// write, for correlating reads across clients.
+    #[inline]
+    pub fn try_latest_write_indexed(&self) -> Option<(usize, T)> {
+        self.queue.try_read_latest_indexed()
+    }
+
+    /// Reads the latest complete write and its index, giving up after
+    /// `timeout` if there is none. Unlike `latest`, this never reads out of
+    /// bounds when nothing has been written.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn latest_timeout_indexed(&self, timeout: Duration) -> Option<(usize, T)> {
+        self.queue.read_latest_timeout_indexed(timeout)
+    }
+
+    /// The index of the most recently earned write. Indices increase by one
+    /// with each push.
+    #[inline]
+    pub fn latest_index(&self) -> usize {
+        self.queue.newest_index()
+    }
+
+    /// The absolute index this client reads next, comparable with
+    /// `latest_index`.
+    #[inline]
+    pub fn position(&self) -> usize {
+        self.to_read
+    }
+
+    /// How many writes this client is behind the newest one. Unlike `len`,
+    /// writes which have been overwritten are counted.
+    #[inline]
+    pub fn lag(&self) -> usize {
+        self.queue.next_write_ptr().saturating_sub(self.to_read)
+    }
+
+    /// Create a `Watcher` of the latest value in the queue. The current latest
+    /// value counts as already seen.
+    pub fn watch(&self) -> Watcher<T> {
+        Watcher {
+            queue: self.queue.clone(),
+            seen: self.latest_index(),
+        }
+    }
+
+    /// Turn this client into an `AutoConsumer`, which jumps to the latest
+    /// write whenever it is lapped.
+    pub fn resubscribe_on_lap(self) -> AutoConsumer<T> {
+        AutoConsumer {
+            client: self,
+            skipped: 0,
+        }
+    }
+
+    /// Whether the queue has been written to so many times that epochs overlap
+    /// the write-in-progress tag. See the crate documentation. Once poisoned,
+    /// pushes are dropped and reads of them behave as if the write never
+    /// completed. `latest` and friends return the last write before poisoning.
+    #[inline]
+    pub fn is_poisoned(&self) -> bool {
+        self.queue.is_poisoned()
+    }
+
+    /// How many more pushes can be made before the queue is poisoned.
+    #[inline]
+    pub fn writes_remaining(&self) -> usize {
+        self.queue.writes_remaining()
+    }
+
+    /// The number of pushes per second since the previous call, across all
+    /// clients of the queue. The first call only records a starting point and
+    /// returns 0.
+    #[cfg(feature = "std")]
+    pub fn push_rate(&mut self) -> f64 {
+        let now = (self.latest_index(), Instant::now());
+        let rate = match self.rate_sample {
+            Some((idx, time)) => {
+                let elapsed = now.1.duration_since(time).as_secs_f64();
+                if elapsed > 0.0 {
+                    (now.0 - idx) as f64 / elapsed
+                } else {
+                    0.0
+                }
+            }
+            None => 0.0,
+        };
+        self.rate_sample = Some(now);
+        rate
+    }
+
+    /// Every client is built here, so that it is counted by
+    /// `Queue::consumer_count` until dropped.
+    fn with_position(queue: Arc<Queue<T>>, to_read: usize, observed: usize) -> Self {
+        queue.consumers.fetch_add(1, Relaxed);
+        QueueClient {
+            queue,
+            to_read,
+            #[cfg(feature = "std")]
+            rate_sample: None,
+            observed,
+            counters: ReadCounters::default(),
+            #[cfg(feature = "std")]
+            cursor: None,
+        }
+    }
+
+    /// Publishes this client's read position to the queue from now on, so
+    /// that pushes overwriting messages it has not read call the hook set by
+    /// `Queue::set_on_evict`. Clones of a registered client are registered
+    /// too. Each read then also stores the position to a shared atomic.
+    #[cfg(feature = "std")]
+    pub fn register_cursor(&mut self) {
+        if self.cursor.is_none() {
+            self.cursor = Some(self.queue.evictions.cursors.register(self.to_read));
+        }
+    }
+
+    /// Stores our read position for `Queue::set_on_evict`, if registered.
+    #[inline]
+    fn publish(&self) {
+        #[cfg(feature = "std")]
+        if let Some(cursor) = &self.cursor {
+            // pairs with the acquire in `Cursors::slowest`
+            cursor.store(self.to_read, Release);
+        }
+    }
+
+    /// Create a client whose next `lag` reads are the `lag` most recent
+    /// writes, so it trails the writers by about `lag` messages. `lag` is
+    /// clamped to the readable history.
+    pub fn fork_with_lag(&self, lag: usize) -> QueueClient<T> {
+        let head = self.queue.next_write_ptr();
+        QueueClient::with_position(
+            self.queue.clone(),
+            head.saturating_sub(lag).max(self.queue.oldest_index(head)),
+            head - 1,
+        )
+    }
+
+    /// Clones this client, keeping its read position, only if that position
+    /// has not been overwritten yet. A clone of a lapped client would drop
+    /// messages on its first read, so the caller may want to resubscribe
+    /// instead.
+    pub fn try_clone(&self) -> Result<QueueClient<T>, Lapped> {
+        if self.to_read < self.queue.oldest_index(self.queue.next_write_ptr()) {
+            return Err(Lapped);
+        }
+        Ok(self.clone_keeping_position())
+    }
+
+    /// Clones this client, starting the clone at this client's read position
+    /// rather than the oldest message still in the history.
+    pub fn clone_keeping_position(&self) -> QueueClient<T> {
+        #[allow(unused_mut)]
+        let mut client =
+            QueueClient::with_position(self.queue.clone(), self.to_read, self.observed);
+        #[cfg(feature = "std")]
+        {
+            client.rate_sample = self.rate_sample;
+            if self.cursor.is_some() {
+                client.register_cursor();
+            }
+        }
+        client
+    }
+
+    /// Splits this client into its queue and the absolute index it would
+    /// read next. `from_parts` puts them back together. The client stops
+    /// counting towards `Queue::consumer_count`.
+    pub fn into_parts(self) -> (Arc<Queue<T>>, usize) {
+        (self.queue.clone(), self.to_read)
+    }
+
+    /// Builds a client of `queue` that reads from the absolute index
+    /// `to_read` next, clamped like `catch_up_to`. Writes before `to_read`
+    /// count as observed.
+    pub fn from_parts(queue: Arc<Queue<T>>, to_read: usize) -> Self {
+        let size = queue.size();
+        let mut client = Self::with_position(queue, size, size - 1);
+        client.catch_up_to(to_read);
+        client.observed = client.to_read - 1;
+        client
+    }
+
+    /// Reads the latest complete write, along with how many writes have
+    /// been started since the previous call. For a new queue, the first call
+    /// counts every write. `value` is `None` if nothing has been written.
+    pub fn observe(&mut self) -> Observation<T> {
+        let latest = self.latest_index();
+        let updates_since_last = latest - self.observed;
+        self.observed = latest;
+        Observation {
+            value: self.queue.read_latest_in_history().map(|(_idx, data)| data),
+            updates_since_last,
+        }
+    }
+
+    /// Reads the write `n` before the most recently initiated one. See
+    /// `Queue::read_back`.
+    #[inline]
+    pub fn read_back(&self, n: usize) -> Result<T, ReadError> {
+        self.queue.read_back(n)
+    }
+
+    /// Takes a snapshot of the history currently held by the queue, without
+    /// copying it. Elements are read lazily, so any that are overwritten
+    /// after the snapshot is taken will read as `None`.
+    #[inline]
+    pub fn snapshot(&self) -> Snapshot<'_, T> {
+        let end = self.queue.next_write_ptr();
+        Snapshot {
+            queue: &self.queue,
+            start: self.queue.oldest_index(end),
+            end,
+        }
     }
 
     /// Create a blocking iterator from this client. Read the documentation
     /// on the two `Iterator` implementations before use.
+    #[allow(clippy::should_implement_trait)]
     pub fn into_iter(self) -> QueueReadIter<T> {
         QueueReadIter(self)
     }
+
+    /// Create a blocking iterator from this client which terminates. Unlike
+    /// `into_iter()`, `None` is yielded once every `Producer` of the queue
+    /// has been dropped and all remaining messages have been read. See
+    /// `ClosingIter`.
+    pub fn into_blocking_iter(self) -> ClosingIter<T> {
+        ClosingIter(self)
+    }
+
+    /// A write-only handle to this queue, counted by
+    /// `Queue::producer_count`.
+    pub fn producer(&self) -> Producer<T> {
+        Producer::new(self.queue.clone())
+    }
+
+    /// Split this client into a write-only `Producer` and a read-only
+    /// `Consumer` of the same queue. The consumer keeps this client's read
+    /// position.
+    pub fn split(self) -> (Producer<T>, Consumer<T>) {
+        (
+            self.producer(),
+            Consumer {
+                client: self,
+                policy: OverrunPolicy::default(),
+                acks: None,
+            },
+        )
+    }
+
+    /// `split`, with a ring of `ack_size` cells through which the consumer
+    /// and its clones acknowledge the indices they have processed, and the
+    /// producer and its clones see the highest one. The ring is a `Queue`
+    /// of indices, so acks are best-effort and lossy like messages.
+    pub fn split_with_acks(self, ack_size: usize) -> (Producer<T>, Consumer<T>) {
+        let acks = Arc::new(Queue::new(ack_size, self.queue.fence));
+        let (mut producer, mut consumer) = self.split();
+        producer.acks = Some(acks.clone());
+        consumer.acks = Some(acks);
+        (producer, consumer)
+    }
+
+    /// `split`, with the producer in an `Arc` so tasks can share it without
+    /// cloning. The consumer stays unique.
+    pub fn into_arc_split(self) -> (Arc<Producer<T>>, Consumer<T>) {
+        let (producer, consumer) = self.split();
+        (Arc::new(producer), consumer)
+    }
 }
 
-trait GetFirst<T> {
-    fn first(self) -> Option<T>;
+/// The result of `QueueClient::consumer_metrics`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConsumerMetrics {
+    /// The absolute index read next, see `QueueClient::position`
+    pub position: usize,
+    /// Writes behind the newest one, see `QueueClient::lag`
+    pub lag: usize,
+    /// Messages skipped because writers lapped the client
+    pub dropped: usize,
+    /// The highest lag seen. Sampled at each lap, every 64 reads and by
+    /// `consumer_metrics`, so short peaks between samples may be missed.
+    pub max_lag: usize,
+    /// How many times the client was lapped and resynced
+    pub reconnects: usize,
 }
 
-impl<T, U> GetFirst<T> for Option<(T, U)> {
+/// The result of `QueueClient::observe`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Observation<T> {
+    /// The latest complete write, if any
+    pub value: Option<T>,
+    /// Writes started since the previous observation
+    pub updates_since_last: usize,
+}
+
+/// The result of `QueueClient::next_status`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NextStatus<T> {
+    /// The next message
+    Got(T),
+    /// Every written message has been read
+    CaughtUp,
+    /// The next message has been claimed by a writer, but not completed
+    HeadInProgress,
+}
+
+/// The error returned when a client's read position has been overwritten.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lapped;
+
+impl core::fmt::Display for Lapped {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("read position has been overwritten by writers")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Lapped {}
+
+/// The error returned by `QueueClient::next_strict` when messages were
+/// overwritten before they were read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gap {
+    /// The first missed index
+    pub from: usize,
+    /// The oldest index still readable, one past the last missed index
+    pub to: usize,
+}
+
+impl core::fmt::Display for Gap {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "messages {} to {} were overwritten before being read",
+            self.from, self.to
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Gap {}
+
+/// The error returned by `Queue::push_checked` when the queue has no writes
+/// left before it would be poisoned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueExhausted;
+
+impl core::fmt::Display for QueueExhausted {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("queue has no writes left before epochs overlap")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for QueueExhausted {}
+
+/// The error returned by `QueueClient::try_next` when messages were
+/// overwritten before they were read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overrun {
+    /// How many messages the read pointer moved past
+    pub skipped: usize,
+}
+
+impl core::fmt::Display for Overrun {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{} messages were overwritten before being read",
+            self.skipped
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Overrun {}
+
+/// Why a read of a specific write failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadError {
+    /// Fewer writes than requested have been made to the queue
+    NotYetWritten,
+    /// The write has been overwritten by a newer one
+    Lapped,
+    /// The write has been claimed but not completed
+    InProgress,
+}
+
+impl core::fmt::Display for ReadError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            ReadError::NotYetWritten => "write has not been made yet",
+            ReadError::Lapped => "write has been overwritten",
+            ReadError::InProgress => "write is in progress",
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ReadError {}
+
+#[cfg(feature = "alloc")]
+impl<T: Copy> Clone for QueueClient<T> {
+    fn clone(&self) -> Self {
+        let mut client = self.clone_keeping_position();
+        client.to_read = self.queue.oldest_index(self.queue.next_write_ptr());
+        client.publish();
+        client
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: Copy> Drop for QueueClient<T> {
+    fn drop(&mut self) {
+        self.queue.consumers.fetch_sub(1, Relaxed);
+        #[cfg(feature = "std")]
+        if let Some(cursor) = &self.cursor {
+            self.queue.evictions.cursors.deregister(cursor);
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: Copy> AsRef<Queue<T>> for QueueClient<T> {
+    fn as_ref(&self) -> &Queue<T> {
+        &self.queue
+    }
+}
+
+#[cfg(feature = "alloc")]
+/// Tracks the latest value in a queue, rather than every message.
+/// Created by `QueueClient::watch`.
+#[derive(Debug, Clone)]
+pub struct Watcher<T: Copy> {
+    queue: Arc<Queue<T>>,
+    /// Index of the last value returned by `borrow`
+    seen: usize,
+}
+
+#[cfg(feature = "alloc")]
+impl<T: Copy> Watcher<T> {
+    /// Reads the latest complete write and marks it as seen. Has the same
+    /// caveats as `QueueClient::latest`.
     #[inline]
-    fn first(self) -> Option<T> {
-        self.map(|(t, _u)| t)
+    pub fn borrow(&mut self) -> T {
+        let (idx, data) = self.queue.read_latest_indexed();
+        self.seen = self.seen.max(idx);
+        data
+    }
+
+    /// Whether a write newer than the last seen value has completed.
+    #[inline]
+    pub fn has_changed(&self) -> bool {
+        self.queue.read_latest_indexed().0 > self.seen
+    }
+
+    /// Busy-waits until a write newer than the last seen value has
+    /// completed. Follow with `borrow` to read it.
+    #[inline]
+    pub fn changed(&self) {
+        let mut backoff = sync::Backoff::default();
+        while !self.has_changed() {
+            backoff.snooze();
+        }
     }
 }
 
-/// Note that the iterator `next` is identical to the ordinary `next`. Because
-/// `None` may be yielded, and then `Some` again later, some iterator methods
-/// may not work normally. Use `into_iter()` for an iterator with more reliable
-/// functionality.
-impl<T: Copy> Iterator for QueueClient<T> {
-    type Item = T;
+#[cfg(feature = "alloc")]
+/// Reads messages in order, but on being lapped skips straight to the latest
+/// write instead of the oldest one still in the history. For consumers that
+/// only care about recent data. Created by `QueueClient::resubscribe_on_lap`.
+///
+/// A clone reads on from the original's position, with its skip count.
+#[derive(Debug)]
+pub struct AutoConsumer<T: Copy> {
+    client: QueueClient<T>,
+    /// Messages skipped over by laps
+    skipped: usize,
+}
+
+#[cfg(feature = "alloc")]
+impl<T: Copy> AutoConsumer<T> {
+    /// Get the next message, or `None` if it has not been written yet. Never
+    /// blocks.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<T> {
+        loop {
+            match self.client.next_strict() {
+                Ok(data) => return data,
+                Err(gap) => {
+                    self.client.reset();
+                    self.skipped += self.client.to_read.saturating_sub(gap.from);
+                }
+            }
+        }
+    }
+
+    /// The total number of messages skipped because of laps.
+    #[inline]
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    /// The underlying client.
+    #[inline]
+    pub fn into_inner(self) -> QueueClient<T> {
+        self.client
+    }
+}
+
+/// A range of consecutive writes claimed by `Queue::reserve_range`. Readers
+/// see every write in the range as in progress until it is committed.
+#[derive(Debug)]
+pub struct RangeToken<
+    'a,
+    T: Copy,
+    #[cfg(feature = "alloc")] D: Storage<T> = Box<[Cell<T>]>,
+    #[cfg(not(feature = "alloc"))] D: Storage<T>,
+> {
+    queue: &'a Queue<T, D>,
+    /// Index of the first claimed write
+    start: usize,
+    len: usize,
+}
+
+impl<'a, T: Copy, D: Storage<T>> RangeToken<'a, T, D> {
+    /// The number of writes in the range.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the range is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The index of the first write in the range.
+    #[inline]
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Sets the `i`th write of the range to `value`.
+    ///
+    /// Panics if `i` is out of the range.
+    #[inline]
+    pub fn set(&mut self, i: usize, value: T) {
+        assert!(i < self.len, "index {} out of range of {}", i, self.len);
+        let idx = self.start + i;
+        if idx & SENTINEL_MASK == 0 {
+            self.queue.cell(idx).data.set(value);
+        }
+    }
+
+    /// Completes every write in the range. Equivalent to dropping the token.
+    pub fn commit(self) {}
+
+    /// The claimed indices which are not poisoned, and so are being written.
+    fn indices(&self) -> impl Iterator<Item = usize> {
+        (self.start..self.start + self.len).filter(|idx| idx & SENTINEL_MASK == 0)
+    }
+}
+
+impl<'a, T: Copy, D: Storage<T>> Drop for RangeToken<'a, T, D> {
+    fn drop(&mut self) {
+        let queue = self.queue;
+        for idx in self.indices() {
+            queue.cell(idx).finish_write(queue.epoch(idx), queue.fence);
+        }
+        queue.notify();
+    }
+}
+
+#[cfg(feature = "alloc")]
+/// The writing half of a `QueueClient`. Created by `QueueClient::split` or
+/// `QueueClient::producer`.
+#[derive(Debug)]
+pub struct Producer<T: Copy> {
+    queue: Arc<Queue<T>>,
+    /// Indices acknowledged by consumers, see `QueueClient::split_with_acks`
+    acks: Option<Arc<Queue<usize>>>,
+}
+
+#[cfg(feature = "alloc")]
+impl<T: Copy> Clone for Producer<T> {
+    fn clone(&self) -> Self {
+        let mut producer = Producer::new(self.queue.clone());
+        producer.acks = self.acks.clone();
+        producer
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: Copy> Drop for Producer<T> {
+    fn drop(&mut self) {
+        // publishes our pushes to whoever sees the count reach 0
+        self.queue.producers.fetch_sub(1, Release);
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: Copy> Producer<T> {
+    fn new(queue: Arc<Queue<T>>) -> Self {
+        queue.producers.fetch_add(1, Relaxed);
+        Producer { queue, acks: None }
+    }
+
+    /// The highest index acknowledged with `Consumer::ack` among the acks
+    /// still in the ack ring, or `None` if there are none or this producer
+    /// was not made by `QueueClient::split_with_acks`. Acks are pushed like
+    /// messages, so a burst of them larger than the ring laps older ones.
+    pub fn highest_acked(&self) -> Option<usize> {
+        let acks = self.acks.as_ref()?;
+        // the first ack is written at `size`, earlier indices are the fill
+        acks.read_range(acks.size(), usize::MAX)
+            .filter_map(Result::ok)
+            .max()
+    }
+
+    /// Push an element onto the end of the queue.
+    #[inline]
+    pub fn push(&self, data: T) {
+        self.queue.push(data)
+    }
+
+    /// Push an element, or return an error if the queue is out of writes.
+    /// See `Queue::push_checked`.
+    #[inline]
+    pub fn push_checked(&self, data: T) -> Result<(), QueueExhausted> {
+        self.queue.push_checked(data)
+    }
+
+    /// Push all of `items` onto the end of the queue as one contiguous run.
+    /// See `Queue::push_batch`.
+    #[inline]
+    pub fn push_batch(&self, items: &[T]) {
+        self.queue.push_batch(items)
+    }
+
+    /// Push the element returned by `make`. See `Queue::push_with`.
+    #[inline]
+    pub fn push_with<F: FnOnce() -> T>(&self, make: F) {
+        self.queue.push_with(make)
+    }
+
+    /// How many clients read this queue. See `Queue::consumer_count`.
+    #[inline]
+    pub fn consumer_count(&self) -> usize {
+        self.queue.consumer_count()
+    }
+}
+
+#[cfg(feature = "alloc")]
+/// The reading half of a `QueueClient`. Created by `QueueClient::split`.
+///
+/// Use `Clone::clone` to fork another read stream, starting at this
+/// consumer's read position.
+#[derive(Debug)]
+pub struct Consumer<T: Copy> {
+    client: QueueClient<T>,
+    /// How `try_next` handles being lapped
+    policy: OverrunPolicy,
+    /// Where `ack` publishes, see `QueueClient::split_with_acks`
+    acks: Option<Arc<Queue<usize>>>,
+}
+
+/// What `Consumer::try_next` does when writers have overwritten messages
+/// the consumer has not read yet. Either way, the messages are lost; the
+/// policy only decides whether the caller is told. Only `try_next` consults
+/// it: `next` and the other reads always resync. Use `BoundedQueueClient`
+/// for a queue that never overwrites unread messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverrunPolicy {
+    /// Return `Overrun` once, then read on from the oldest message kept.
+    #[default]
+    Error,
+    /// Skip to the oldest message kept and read it, as `next` does.
+    Resync,
+}
+
+#[cfg(feature = "alloc")]
+impl<T: Copy> Consumer<T> {
+    /// Get the next message, skipping any that were overwritten before they
+    /// were read. See `QueueClient::next`.
+    #[inline]
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<T> {
+        self.next_with_lag().map(|(data, _)| data)
+    }
+
+    /// The same as `next`, but also returns how many messages were skipped
+    /// since the previous read.
+    #[inline]
+    pub fn next_with_lag(&mut self) -> Option<(T, usize)> {
+        self.client.next()
+    }
+
+    /// See `QueueClient::next_blocking`.
+    #[inline]
+    pub fn next_blocking(&mut self) -> (T, usize) {
+        self.client.next_blocking()
+    }
+
+    /// See `QueueClient::next_with_seq`.
+    #[inline]
+    pub fn next_with_seq(&mut self) -> Option<(usize, T)> {
+        self.client.next_with_seq()
+    }
+
+    /// The same as `next`, but with the overrun policy chosen by
+    /// `set_overrun_policy`. By default, being lapped is reported like
+    /// `QueueClient::try_next`.
+    #[inline]
+    pub fn try_next(&mut self) -> Result<Option<T>, Overrun> {
+        match self.policy {
+            OverrunPolicy::Error => self.client.try_next(),
+            OverrunPolicy::Resync => Ok(self.next()),
+        }
+    }
+
+    /// Sets how `try_next` handles being lapped. Clones keep the policy.
+    /// `next` and the other reads always resync, and report the skipped
+    /// messages as lag where they return it.
+    #[inline]
+    pub fn set_overrun_policy(&mut self, policy: OverrunPolicy) {
+        self.policy = policy;
+    }
+
+    /// How `try_next` handles being lapped.
+    #[inline]
+    pub fn overrun_policy(&self) -> OverrunPolicy {
+        self.policy
+    }
+
+    /// Tells the producer that the message at `index`, as returned by
+    /// `next_with_seq`, has been processed. Does nothing unless this
+    /// consumer was made by `QueueClient::split_with_acks`.
+    #[inline]
+    pub fn ack(&self, index: usize) {
+        if let Some(acks) = &self.acks {
+            acks.push(index);
+        }
+    }
+
+    /// See `QueueClient::next_n`.
+    #[inline]
+    pub fn next_n(&mut self, out: &mut [T]) -> usize {
+        self.client.next_n(out)
+    }
+
+    /// See `QueueClient::next_wait`.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn next_wait(&mut self) -> T {
+        self.client.next_wait()
+    }
+
+    /// See `QueueClient::next_wait_timeout`.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn next_wait_timeout(&mut self, timeout: Duration) -> Option<T> {
+        self.client.next_wait_timeout(timeout)
+    }
+
+    /// See `QueueClient::latest`.
+    #[inline]
+    pub fn latest(&self) -> T {
+        self.client.latest()
+    }
+
+    /// See `QueueClient::peek`.
+    #[inline]
+    pub fn peek(&self) -> Option<T> {
+        self.client.peek()
+    }
+
+    /// See `QueueClient::position`.
+    #[inline]
+    pub fn position(&self) -> usize {
+        self.client.position()
+    }
+
+    /// See `QueueClient::lag`.
+    #[inline]
+    pub fn lag(&self) -> usize {
+        self.client.lag()
+    }
+
+    /// See `QueueClient::consumer_metrics`.
+    #[inline]
+    pub fn consumer_metrics(&self) -> ConsumerMetrics {
+        self.client.consumer_metrics()
+    }
+
+    /// See `QueueClient::reset_stats`.
+    #[inline]
+    pub fn reset_stats(&mut self) {
+        self.client.reset_stats()
+    }
+
+    /// See `QueueClient::catch_up`.
+    #[inline]
+    pub fn catch_up(&mut self, margin: usize) {
+        self.client.catch_up(margin)
+    }
+
+    /// See `QueueClient::catch_up_latest`.
+    #[inline]
+    pub fn catch_up_latest(&mut self) {
+        self.client.catch_up_latest()
+    }
+
+    /// See `QueueClient::catch_up_to_oldest`.
+    #[inline]
+    pub fn catch_up_to_oldest(&mut self) {
+        self.client.catch_up_to_oldest()
+    }
+
+    /// An iterator over the messages available now, which ends at the first
+    /// `next()` that returns `None`.
+    #[inline]
+    pub fn drain(&mut self) -> Drain<'_, T> {
+        Drain(&mut self.client)
+    }
+
+    /// An iterator which busy-waits for each message like `next_blocking()`.
+    /// It never ends.
+    #[inline]
+    pub fn iter_blocking(&mut self) -> IterBlocking<'_, T> {
+        IterBlocking(&mut self.client)
+    }
+
+    /// Applies `f` to each message as it is read. Laps are skipped and
+    /// reported like `next_with_lag`.
+    pub fn map<U, F: FnMut(T) -> U>(self, f: F) -> MappedConsumer<T, U, F> {
+        MappedConsumer {
+            consumer: self,
+            f,
+            _out: PhantomData,
+        }
+    }
+
+    /// Only yields messages for which `predicate` returns `true`. Rejected
+    /// messages are read and passed over like any other, and laps are still
+    /// reported by `next_with_lag`.
+    pub fn filter<P: FnMut(&T) -> bool>(self, predicate: P) -> FilteredConsumer<T, P> {
+        FilteredConsumer {
+            consumer: self,
+            predicate,
+        }
+    }
+
+    /// A blocking iterator which ends once every `Producer` of the queue has
+    /// been dropped and everything has been read. See `ClosingIter`.
+    pub fn into_blocking_iter(self) -> ClosingIter<T> {
+        ClosingIter(self.client)
+    }
+
+    /// A `Stream` of messages, for reading from an async task. When there is
+    /// nothing to read, the task is woken by the next push. Laps are skipped
+    /// like `next()`. It never ends.
+    #[cfg(feature = "async")]
+    #[inline]
+    pub fn into_stream(self) -> ConsumerStream<T> {
+        ConsumerStream(self.client)
+    }
+}
+
+/// A `Consumer` which transforms each message it reads. Created by
+/// `Consumer::map`.
+#[cfg(feature = "alloc")]
+pub struct MappedConsumer<T: Copy, U, F> {
+    consumer: Consumer<T>,
+    f: F,
+    _out: PhantomData<fn() -> U>,
+}
+
+#[cfg(feature = "alloc")]
+impl<T: Copy, U, F: FnMut(T) -> U> MappedConsumer<T, U, F> {
+    /// See `Consumer::next`.
+    #[inline]
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<U> {
+        self.consumer.next().map(&mut self.f)
+    }
+
+    /// See `Consumer::next_with_lag`.
+    #[inline]
+    pub fn next_with_lag(&mut self) -> Option<(U, usize)> {
+        let (data, lag) = self.consumer.next_with_lag()?;
+        Some(((self.f)(data), lag))
+    }
+
+    /// See `Consumer::next_blocking`.
+    #[inline]
+    pub fn next_blocking(&mut self) -> (U, usize) {
+        let (data, lag) = self.consumer.next_blocking();
+        ((self.f)(data), lag)
+    }
+
+    /// Returns the consumer, at its current read position.
+    pub fn into_inner(self) -> Consumer<T> {
+        self.consumer
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: Copy + core::fmt::Debug, U, F> core::fmt::Debug for MappedConsumer<T, U, F> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("MappedConsumer")
+            .field("consumer", &self.consumer)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A `Consumer` which passes over messages failing a predicate. Created by
+/// `Consumer::filter`.
+#[cfg(feature = "alloc")]
+pub struct FilteredConsumer<T: Copy, P> {
+    consumer: Consumer<T>,
+    predicate: P,
+}
+
+#[cfg(feature = "alloc")]
+impl<T: Copy, P: FnMut(&T) -> bool> FilteredConsumer<T, P> {
+    /// Get the next message passing the predicate, or `None` if there are
+    /// none left to read. See `Consumer::next`.
+    #[inline]
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<T> {
+        self.next_with_lag().map(|(data, _)| data)
+    }
+
+    /// The same as `next`, but also returns how many messages were skipped
+    /// by laps since the previous message returned, including any while
+    /// reading rejected messages. Rejected messages are not counted.
+    #[inline]
+    pub fn next_with_lag(&mut self) -> Option<(T, usize)> {
+        let mut skipped = 0;
+        loop {
+            let (data, lag) = self.consumer.next_with_lag()?;
+            skipped += lag;
+            if (self.predicate)(&data) {
+                return Some((data, skipped));
+            }
+        }
+    }
+
+    /// Busy-waits for the next message passing the predicate. The lag is
+    /// counted like `next_with_lag`.
+    #[inline]
+    pub fn next_blocking(&mut self) -> (T, usize) {
+        let mut skipped = 0;
+        loop {
+            let (data, lag) = self.consumer.next_blocking();
+            skipped += lag;
+            if (self.predicate)(&data) {
+                return (data, skipped);
+            }
+        }
+    }
+
+    /// Returns the consumer, at its current read position.
+    pub fn into_inner(self) -> Consumer<T> {
+        self.consumer
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: Copy + core::fmt::Debug, P> core::fmt::Debug for FilteredConsumer<T, P> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("FilteredConsumer")
+            .field("consumer", &self.consumer)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Yields messages until there are none left to read. Created by
+/// `Consumer::drain`.
+#[cfg(feature = "alloc")]
+#[derive(Debug)]
+pub struct Drain<'a, T: Copy>(&'a mut QueueClient<T>);
+
+#[cfg(feature = "alloc")]
+impl<'a, T: Copy> Iterator for Drain<'a, T> {
+    type Item = T;
+    #[inline]
+    fn next(&mut self) -> Option<T> {
+        self.0.next().first()
+    }
+}
+
+/// Yields every message, busy-waiting for each. `None` is never yielded.
+/// Created by `Consumer::iter_blocking`.
+#[cfg(feature = "alloc")]
+#[derive(Debug)]
+pub struct IterBlocking<'a, T: Copy>(&'a mut QueueClient<T>);
+
+#[cfg(feature = "alloc")]
+impl<'a, T: Copy> Iterator for IterBlocking<'a, T> {
+    type Item = T;
+    #[inline]
+    fn next(&mut self) -> Option<T> {
+        Some(self.0.next_blocking().0)
+    }
+}
+
+/// Yields every message, waiting for each with `QueueClient::poll_next_waker`.
+/// `None` is never yielded. Created by `Consumer::into_stream`.
+#[cfg(feature = "async")]
+#[derive(Debug)]
+pub struct ConsumerStream<T: Copy>(QueueClient<T>);
+
+#[cfg(feature = "async")]
+impl<T: Copy> futures_core::Stream for ConsumerStream<T> {
+    type Item = T;
+    #[inline]
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Option<T>> {
+        self.get_mut().0.poll_next_waker(cx.waker()).map(Some)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: Copy> Clone for AutoConsumer<T> {
+    fn clone(&self) -> Self {
+        AutoConsumer {
+            client: self.client.clone_keeping_position(),
+            skipped: self.skipped,
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: Copy> Clone for Consumer<T> {
+    fn clone(&self) -> Self {
+        Consumer {
+            client: self.client.clone_keeping_position(),
+            policy: self.policy,
+            acks: self.acks.clone(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+/// The published read positions of the clients registered with a queue,
+/// see `QueueClient::register_cursor`.
+#[derive(Debug, Default)]
+struct Cursors(Mutex<Vec<Arc<AtomicUsize>>>);
+
+#[cfg(feature = "std")]
+impl Cursors {
+    fn register(&self, position: usize) -> Arc<AtomicUsize> {
+        let cursor = Arc::new(AtomicUsize::new(position));
+        self.lock().push(cursor.clone());
+        cursor
+    }
+
+    fn deregister(&self, cursor: &Arc<AtomicUsize>) {
+        self.lock().retain(|c| !Arc::ptr_eq(c, cursor));
+    }
+
+    /// The read position of the slowest registered client.
+    fn slowest(&self) -> Option<usize> {
+        // pairs with the release in `QueueClient::publish`, so the reads
+        // before it are done before we overwrite what they read
+        self.lock().iter().map(|c| c.load(Acquire)).min()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Arc<AtomicUsize>>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(feature = "std")]
+type EvictHook<T> = Arc<dyn Fn(usize, T) + Send + Sync>;
+
+#[cfg(feature = "std")]
+/// The state behind `Queue::set_on_evict`.
+struct Evictions<T> {
+    hook: Mutex<Option<EvictHook<T>>>,
+    /// Set once there is a hook, so pushes only take the lock when needed
+    armed: AtomicBool,
+    /// Read positions of clients which called `QueueClient::register_cursor`
+    cursors: Cursors,
+}
+
+#[cfg(feature = "std")]
+impl<T> Evictions<T> {
+    fn new() -> Self {
+        Evictions {
+            hook: Mutex::new(None),
+            armed: AtomicBool::new(false),
+            cursors: Cursors::default(),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<EvictHook<T>>> {
+        self.hook.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T> std::fmt::Debug for Evictions<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Evictions")
+            .field("armed", &self.armed)
+            .field("cursors", &self.cursors)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "std")]
+/// A client of a lossless queue: pushes wait for the slowest client to read
+/// the message they would overwrite, instead of overtaking it.
+///
+/// Each client registers its read position with the queue when it is
+/// created or cloned, publishes it after every read, and deregisters it when
+/// dropped. A clone starts at the original's position. The queue can't be
+/// reached except through these clients, so every reader is registered.
+///
+/// A client which stops reading stalls every push once the queue is full.
+/// `push` then waits forever, so a thread which pushes to a full queue
+/// while holding a client that it is the only one to read from deadlocks.
+/// Threads that only write should use a `BoundedProducer`, which is not
+/// registered, and `try_push` returns the message instead of waiting.
+///
+/// Pushes find the slowest client under a lock, so they are slower than
+/// those of a `QueueClient`. Waiting pushes spin, yielding to other threads.
+#[derive(Debug)]
+pub struct BoundedQueueClient<T: Copy> {
+    /// Registered with `QueueClient::register_cursor`
+    client: QueueClient<T>,
+    producer: BoundedProducer<T>,
+}
+
+#[cfg(feature = "std")]
+/// The writing half of a bounded queue, which does not hold back pushes.
+/// Created by `BoundedQueueClient::producer`.
+#[derive(Debug)]
+pub struct BoundedProducer<T: Copy> {
+    queue: Arc<Queue<T>>,
+}
+
+#[cfg(feature = "std")]
+impl<T: Copy> Clone for BoundedProducer<T> {
+    fn clone(&self) -> Self {
+        BoundedProducer::new(self.queue.clone())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Copy> Drop for BoundedProducer<T> {
+    fn drop(&mut self) {
+        // see `Producer`'s drop
+        self.queue.producers.fetch_sub(1, Release);
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Copy + Default> BoundedQueueClient<T> {
+    /// Creates a bounded queue holding at least `size` unread messages,
+    /// with this as its only client.
+    pub fn new_queue(size: usize) -> Self {
+        let mut client = QueueClient::new_queue(size);
+        client.register_cursor();
+        BoundedQueueClient {
+            producer: BoundedProducer::new(client.queue.clone()),
+            client,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Copy> BoundedQueueClient<T> {
+    /// The number of messages the queue can hold.
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.client.size()
+    }
+
+    /// Push an element onto the end of the queue, waiting until every client
+    /// has read the message it would overwrite.
+    #[inline]
+    pub fn push(&self, data: T) {
+        self.producer.push(data)
+    }
+
+    /// Push an element unless that would overwrite a message some client
+    /// has not read, returning it back if so.
+    #[inline]
+    pub fn try_push(&self, data: T) -> Result<(), T> {
+        self.producer.try_push(data)
+    }
+
+    /// Get the next message, or `None` if it has not been written or is
+    /// being written. Never skips messages.
+    #[inline]
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<T> {
+        self.client.next().map(|(data, _)| data)
+    }
+
+    /// The same as `next()`, but parks the thread until there is a message.
+    /// See `QueueClient::next_wait`.
+    pub fn next_wait(&mut self) -> T {
+        self.client.next_wait()
+    }
+
+    /// A writer which is not registered as a reader.
+    #[inline]
+    pub fn producer(&self) -> BoundedProducer<T> {
+        self.producer.clone()
+    }
+
+    /// How many clients read this queue, each holding back pushes until it
+    /// has read what they would overwrite. See `Queue::consumer_count`.
+    #[inline]
+    pub fn consumer_count(&self) -> usize {
+        self.client.queue.consumer_count()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Copy> Clone for BoundedQueueClient<T> {
+    fn clone(&self) -> Self {
+        // our own cursor holds back pushes until the clone is registered
+        BoundedQueueClient {
+            client: self.client.clone_keeping_position(),
+            producer: self.producer.clone(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Copy> BoundedProducer<T> {
+    fn new(queue: Arc<Queue<T>>) -> Self {
+        queue.producers.fetch_add(1, Relaxed);
+        BoundedProducer { queue }
+    }
+
+    /// See `BoundedQueueClient::push`.
+    pub fn push(&self, mut data: T) {
+        let mut backoff = sync::Backoff::default();
+        while let Err(back) = self.try_push(data) {
+            data = back;
+            backoff.snooze();
+        }
+    }
+
+    /// See `BoundedQueueClient::try_push`.
+    pub fn try_push(&self, data: T) -> Result<(), T> {
+        let limit = match self.queue.evictions.cursors.slowest() {
+            Some(slowest) => slowest + self.queue.size(),
+            None => usize::MAX,
+        };
+        self.queue.try_push_below(data, limit)
+    }
+}
+
+#[cfg(feature = "alloc")]
+/// A window over the queue history, oldest first. Created by
+/// `QueueClient::snapshot`.
+#[derive(Debug)]
+pub struct Snapshot<'a, T: Copy> {
+    queue: &'a Queue<T>,
+    start: usize,
+    end: usize,
+}
+
+#[cfg(feature = "alloc")]
+impl<'a, T: Copy> Snapshot<'a, T> {
+    /// The number of elements in the window.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// Whether the queue had been written to when the snapshot was taken.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Reads the `i`th oldest element of the window. Returns `None` if `i`
+    /// is out of bounds, or if the element has since been overwritten or is
+    /// still being written.
+    #[inline]
+    pub fn get(&self, i: usize) -> Option<T> {
+        if i >= self.len() {
+            return None;
+        }
+        self.queue.read(self.start + i).ok()
+    }
+}
+
+/// Pads and aligns a value to the length of a cache line.
+///
+/// Clients are small, so several stored next to each other share a cache
+/// line. If they are advanced by different threads, every read invalidates
+/// the line for the others. Wrap each one in `CachePadded` to avoid this.
+///
+/// Like `crossbeam-utils`, this assumes 128 byte lines on x86_64 and aarch64
+/// to account for adjacent line prefetching, and 64 bytes elsewhere.
+#[cfg_attr(any(target_arch = "x86_64", target_arch = "aarch64"), repr(align(128)))]
+#[cfg_attr(
+    not(any(target_arch = "x86_64", target_arch = "aarch64")),
+    repr(align(64))
+)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CachePadded<T>(T);
+
+impl<T> CachePadded<T> {
+    pub const fn new(t: T) -> Self {
+        CachePadded(t)
+    }
+
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> core::ops::Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> core::ops::DerefMut for CachePadded<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+#[cfg(feature = "alloc")]
+trait GetFirst<T> {
+    fn first(self) -> Option<T>;
+}
+
+#[cfg(feature = "alloc")]
+impl<T, U> GetFirst<T> for Option<(T, U)> {
+    #[inline]
+    fn first(self) -> Option<T> {
+        self.map(|(t, _u)| t)
+    }
+}
+
+/// Note that the iterator `next` is identical to the ordinary `next`. Because
+/// `None` may be yielded, and then `Some` again later, some iterator methods
+/// may not work normally. Use `into_iter()` for an iterator with more reliable
+/// functionality.
+#[cfg(feature = "alloc")]
+impl<T: Copy> Iterator for QueueClient<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        QueueClient::next(self).first()
+    }
+
+    fn count(self) -> usize {
+        unimplemented!()
+    }
+
+    fn nth(&mut self, n: usize) -> Option<T> {
+        QueueClient::skip(self, n);
+        <Self as Iterator>::next(self)
+    }
+}
+
+#[cfg(feature = "alloc")]
+pub struct QueueReadIter<T: Copy>(QueueClient<T>);
+/// Here, `next` is identical to `QueueClient::next_blocking`. `None` is NEVER
+/// yielded, so `for_each` and similar methods will never terminate.
+#[cfg(feature = "alloc")]
+impl<T: Copy> Iterator for QueueReadIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        Some(QueueClient::next_blocking(&mut self.0).0)
+    }
+
+    fn count(self) -> usize {
+        unimplemented!()
+    }
+
+    fn nth(&mut self, n: usize) -> Option<T> {
+        QueueClient::skip(&mut self.0, n);
+        self.next()
+    }
+}
+
+#[cfg(feature = "alloc")]
+/// A blocking iterator which ends once the producers are gone. Created by
+/// `QueueClient::into_blocking_iter` or `Consumer::into_blocking_iter`.
+///
+/// Only `Producer`s and `BoundedProducer`s keep it going, as counted by
+/// `Queue::producer_count`. Other clients, consumers and watchers don't,
+/// even though a `QueueClient` can push, so push through a `Producer`
+/// from `QueueClient::producer` or `split`. If no producer has been
+/// created yet, the iterator ends as soon as it has read everything.
+#[derive(Debug)]
+pub struct ClosingIter<T: Copy>(QueueClient<T>);
+/// Here, `next` busy-waits like `QueueClient::next_blocking`, but yields `None`
+/// once there are no producers left and there is nothing left to read.
+#[cfg(feature = "alloc")]
+impl<T: Copy> Iterator for ClosingIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let mut backoff = sync::Backoff::default();
+        loop {
+            if let Some((data, _)) = QueueClient::next(&mut self.0) {
+                return Some(data);
+            }
+            if self.0.queue.producer_count() == 0 {
+                // the last producer may have pushed between our read and its
+                // drop, and the count's acquire load makes that push visible
+                return QueueClient::next(&mut self.0).first();
+            }
+            backoff.snooze();
+        }
+    }
+}
+
+// `next_power_of_two` silently wraps to 0 on overflow in release builds,
+// which would leave us with an empty buffer and a nonsense mask
+#[cfg(feature = "alloc")]
+const fn round_up_to_power_of_two(u: usize) -> usize {
+    match u.checked_next_power_of_two() {
+        Some(p) => p,
+        None => panic!("queue size overflows usize when rounded to a power of two"),
+    }
+}
+
+#[cfg(all(test, feature = "std", not(loom)))]
+mod tests {
+    use super::*;
+    #[test]
+    fn rounding() {
+        assert_eq!(round_up_to_power_of_two(1), 1);
+        assert_eq!(round_up_to_power_of_two(5), 8);
+        assert_eq!(round_up_to_power_of_two(11), 16);
+        assert_eq!(round_up_to_power_of_two(15), 16);
+        assert_eq!(round_up_to_power_of_two(16), 16);
+        assert_eq!(round_up_to_power_of_two(17), 32);
+        assert_eq!(round_up_to_power_of_two(28), 32);
+        assert_eq!(round_up_to_power_of_two(56), 64);
+        assert_eq!(round_up_to_power_of_two(45), 64);
+        assert_eq!(round_up_to_power_of_two(100), 128);
+        assert_eq!(round_up_to_power_of_two(128), 128);
+        assert_eq!(round_up_to_power_of_two(423), 512);
+        assert_eq!(round_up_to_power_of_two(1000), 1024);
+        assert_eq!(round_up_to_power_of_two(2000), 2048);
+        assert_eq!(
+            round_up_to_power_of_two(1_152_921_504_606_846_000),
+            1_152_921_504_606_846_976
+        );
+        assert_eq!(
+            round_up_to_power_of_two(9_223_372_036_854_000_000),
+            9_223_372_036_854_775_808
+        );
+    }
+
+    #[test]
+    fn rounding_overflow_boundary() {
+        let largest = usize::MAX / 2 + 1;
+        assert_eq!(round_up_to_power_of_two(largest - 1), largest);
+        assert_eq!(round_up_to_power_of_two(largest), largest);
+    }
+
+    #[test]
+    #[should_panic]
+    fn rounding_overflow() {
+        round_up_to_power_of_two(usize::MAX / 2 + 2);
+    }
+
+    fn get_incrementor() -> impl Iterator<Item = u32> {
+        std::iter::successors(Some(1), |n| Some(n + 1))
+    }
+
+    #[derive(Debug, Default, Clone)]
+    struct Chomp(Option<u32>);
+
+    impl Chomp {
+        fn eat(&mut self, u: u32) {
+            // dbg!((u, self.0));
+            match self.0 {
+                None => (),
+                Some(x) if x == u - 1 => (),
+                Some(_) => panic!(),
+            }
+            self.0 = Some(u);
+        }
+    }
+
+    /// Checks messages from several producers, where producer `tag` pushes
+    /// `tag << 24 | n` for `n` counting up from 1. However the producers
+    /// interleave, each one's values must arrive in order with no holes or
+    /// duplicates.
+    #[derive(Debug)]
+    struct Interleaved(Vec<Chomp>);
+
+    impl Interleaved {
+        fn new(producers: u32) -> Self {
+            Interleaved(vec![Chomp(Some(0)); producers as usize])
+        }
+
+        fn eat(&mut self, x: u32) {
+            self.0[(x >> 24) as usize].eat(x & 0xff_ffff);
+        }
+
+        fn assert_complete(&self, pushes: u32) {
+            for ch in &self.0 {
+                assert_eq!(ch.0, Some(pushes));
+            }
+        }
+    }
+
+    fn write(q: &QueueClient<u32>, data_iter: &mut impl Iterator<Item = u32>, n: u32) {
+        for _ in 0..n {
+            q.push(data_iter.next().unwrap());
+        }
+    }
+
+    fn read(q: &mut QueueClient<u32>, ch: &mut Chomp, n: u32) {
+        for _ in 0..n {
+            ch.eat(q.next_blocking().0);
+        }
+    }
+
+    #[test]
+    fn single_threaded_single_client() {
+        let q = &mut QueueClient::new_queue(100);
+        let di = &mut get_incrementor();
+        let ch = &mut Chomp::default();
+        write(q, di, 20);
+        read(q, ch, 20);
+        write(q, di, 30);
+        read(q, ch, 20);
+        write(q, di, 40);
+        read(q, ch, 50);
+        assert_eq!(q.next(), None);
+        write(q, di, 1);
+        read(q, ch, 1);
+    }
+
+    #[test]
+    fn channel_aliases() {
+        let q = &mut QueueClient::new_queue(100);
+        let di = &mut get_incrementor();
+        let ch = &mut Chomp::default();
+        for _ in 0..20 {
+            q.send(di.next().unwrap());
+        }
+        for _ in 0..20 {
+            ch.eat(q.recv_blocking());
+        }
+        for _ in 0..30 {
+            q.send(di.next().unwrap());
+        }
+        for _ in 0..30 {
+            ch.eat(q.recv().unwrap());
+        }
+        assert_eq!(q.recv(), None);
+    }
+
+    #[test]
+    fn single_threaded_multi_client() {
+        let q1 = &mut QueueClient::new_queue(100);
+        let q2 = &mut Clone::clone(q1);
+        let di = &mut get_incrementor();
+        let c1 = &mut Chomp::default();
+        let c2 = &mut Chomp::default();
+
+        // indivudal
+        write(q1, di, 20);
+        read(q1, c1, 20);
+        read(q2, c2, 20);
+
+        // exchanging
+        write(q1, di, 20);
+        read(q2, c2, 20);
+        write(q2, di, 20);
+        read(q1, c1, 20);
+        read(q2, c2, 20);
+        read(q1, c1, 20);
+
+        assert_eq!(q1.next(), None);
+        write(q1, di, 1);
+        read(q1, c1, 1);
+        read(q2, c2, 1);
+
+        assert_eq!(q2.next(), None);
+        write(q2, di, 1);
+        read(q2, c2, 1);
+        read(q1, c1, 1);
+
+        assert_eq!(q2.next(), None);
+        assert_eq!(q1.next(), None);
+
+        write(q1, di, 500);
+        // 562 written so far
+        // default read margin is 1
+        // read 128 back, meaning
+        assert_eq!(q2.next().first(), Some(62 + 500 - 127 + 1));
+        assert_eq!(q1.latest(), 62 + 500);
+    }
+
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+    use std::thread;
+    use std::time::Duration;
+    #[test]
+    fn multithreaded_single_producer() {
+        let mut q1 = QueueClient::<u32>::new_queue(4000);
+        let mut q2 = q1.clone();
+        let q3 = q1.clone();
+        let q4 = q1.clone();
+
+        let messages = q1.size() * 20;
+        let write_delay = 25; // nanos
+
+        // simple reader
+        let t1 = thread::spawn(move || {
+            let mut c1 = Chomp::default();
+            for _ in 0..messages {
+                c1.eat(q1.next_blocking().0);
+            }
+        });
+        // reader joins late and will "catch up"
+        let t2 = thread::spawn(move || {
+            let mut c2 = Chomp::default();
+            let skip_first = (q2.size() as u64) * 5;
+            thread::sleep(Duration::from_nanos(write_delay * skip_first));
+            let skip_first = skip_first * 3 / 2; //1.5x margin to ensure we don't just deadlock
+            for _ in 0..(messages - skip_first as usize) {
+                c2.eat(q2.next_blocking().0);
+            }
+        });
+        // reader ensures latest is at least monotonic
+        let end_thread = Arc::new(AtomicBool::new(false));
+        let stop = end_thread.clone();
+        let t3 = thread::spawn(move || {
+            let mut last = 0;
+            while !stop.load(Ordering::Relaxed) {
+                let r = q3.latest();
+                assert!(r >= last, "{} >= {}", r, last);
+                last = r;
+            }
+        });
+
+        for data in 0..messages {
+            q4.push(data as u32);
+            thread::sleep(Duration::from_nanos(write_delay));
+        }
+
+        end_thread.store(true, Ordering::Relaxed);
+        t1.join().unwrap();
+        t2.join().unwrap();
+        t3.join().unwrap();
+    }
+
+    fn spawn_producers(
+        producers: u32,
+        pushes: u32,
+        push: impl Fn(u32) + Clone + Send + 'static,
+    ) -> Vec<thread::JoinHandle<()>> {
+        (0..producers)
+            .map(|tag| {
+                let push = push.clone();
+                thread::spawn(move || {
+                    for n in 1..=pushes {
+                        push(tag << 24 | n);
+                    }
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn map_and_filter_consumers() {
+        let (producer, consumer) = QueueClient::<u32>::new_queue(4).split();
+        let mut doubled = consumer.clone().map(|x| x * 2);
+        let mut odd = consumer.filter(|x| x % 2 == 1);
+        assert_eq!(doubled.next(), None);
+        assert_eq!(odd.next(), None);
+        for x in 1..=3 {
+            producer.push(x);
+        }
+        assert_eq!(doubled.next_blocking(), (2, 0));
+        assert_eq!(odd.next_with_lag(), Some((1, 0)));
+        assert_eq!(odd.next(), Some(3));
+        assert_eq!(odd.next(), None);
+        // the lap is found reading the rejected 8, but still counts
+        for x in 4..=10 {
+            producer.push(x);
+        }
+        assert_eq!(odd.next_with_lag(), Some((9, 4)));
+        let mut doubled = doubled.into_inner().map(|x| x + 1);
+        assert_eq!(doubled.next_with_lag(), Some((9, 6)));
+    }
+
+    #[test]
+    fn cell_try_write_needs_previous_epoch() {
+        let cell = Cell::<u32>::default();
+        assert!(cell.try_write(1, 4, 4, WriterFence::Release));
+        assert!(!cell.try_write(2, 12, 4, WriterFence::Release));
+        cell.begin_write(8, 4);
+        // the previous writer is mid-write, and the failed attempt leaves no trace
+        assert!(!cell.try_write(3, 12, 4, WriterFence::Release));
+        assert_eq!(cell.epoch.load(Relaxed), 8 | SENTINEL_MASK);
+        assert_eq!(cell.read(), 1);
+        cell.finish_write(8, WriterFence::Release);
+        assert!(cell.try_write(3, 12, 4, WriterFence::Release));
+        assert_eq!(cell.read_validated(12), Ok(3));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "gave up waiting")]
+    fn cell_write_stuck_predecessor() {
+        let cell = Cell::<u32>::default();
+        // the write of epoch 4 never happened
+        cell.write(1, 8, 4, WriterFence::Release);
+    }
+
+    #[test]
+    fn fast_path_never_locks_wakers() {
+        let q = QueueClient::<u32>::new_queue(4);
+        let mut r = q.clone();
+        let queue: &Queue<u32> = q.as_ref();
+        // if a push or a read with data ready took the lock, it would block
+        let wakers = queue.wakers.lock().unwrap();
+        let (tx, rx) = std::sync::mpsc::channel();
+        let t = thread::spawn(move || {
+            r.push(1);
+            tx.send(r.next_wait()).unwrap();
+        });
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok(1));
+        assert!(wakers.is_empty());
+        assert!(!queue.waiting.load(Relaxed));
+        drop(wakers);
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn consumer_count_tracks_clients() {
+        let (producer, consumer) = QueueClient::<u32>::new_queue(4).split();
+        assert_eq!(producer.consumer_count(), 1);
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let consumer = consumer.clone();
+                thread::spawn(move || {
+                    for _ in 0..1000 {
+                        let clients = (consumer.clone(), consumer.clone());
+                        drop(clients);
+                    }
+                    consumer
+                })
+            })
+            .collect();
+        let consumers: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+        assert_eq!(producer.consumer_count(), 5);
+        drop(consumers);
+        drop(consumer);
+        // nobody listening, but pushes still work
+        assert_eq!(producer.consumer_count(), 0);
+        producer.push(1);
+
+        let (queue, to_read) = QueueClient::<u32>::new_queue(4).into_parts();
+        assert_eq!(queue.consumer_count(), 0);
+        let client = QueueClient::from_parts(queue, to_read);
+        assert_eq!(client.as_ref().consumer_count(), 1);
+    }
+
+    #[test]
+    fn multithreaded_multi_producer() {
+        const PRODUCERS: u32 = 4;
+        const PUSHES: u32 = 10_000;
+        // big enough that the reader is never lapped
+        let mut q = QueueClient::<u32>::new_queue((PRODUCERS * PUSHES) as usize);
+        let w = q.clone();
+        let producers = spawn_producers(PRODUCERS, PUSHES, move |x| w.push(x));
+
+        let mut check = Interleaved::new(PRODUCERS);
+        let mut last_idx = None;
+        for _ in 0..PRODUCERS * PUSHES {
+            let (idx, x) = loop {
+                if let Some(msg) = q.next_with_seq() {
+                    break msg;
+                }
+            };
+            // every index is written exactly once
+            if let Some(last) = last_idx {
+                assert_eq!(idx, last + 1);
+            }
+            last_idx = Some(idx);
+            check.eat(x);
+        }
+        for t in producers {
+            t.join().unwrap();
+        }
+        check.assert_complete(PUSHES);
+        assert_eq!(q.next(), None);
+    }
+
+    #[test]
+    fn multithreaded_multi_producer_bounded() {
+        const PRODUCERS: u32 = 4;
+        const PUSHES: u32 = 10_000;
+        // small, so the producers keep reusing cells
+        let mut q = BoundedQueueClient::<u32>::new_queue(8);
+        let w = q.producer();
+        let producers = spawn_producers(PRODUCERS, PUSHES, move |x| w.push(x));
+
+        let mut check = Interleaved::new(PRODUCERS);
+        for _ in 0..PRODUCERS * PUSHES {
+            check.eat(q.next_wait());
+        }
+        for t in producers {
+            t.join().unwrap();
+        }
+        check.assert_complete(PUSHES);
+        assert_eq!(q.next(), None);
+    }
+
+    #[test]
+    fn snapshot_reflects_overwrites() {
+        let q = QueueClient::<u32>::new_queue(4);
+        assert!(q.snapshot().is_empty());
+        write(&q, &mut get_incrementor(), 3);
+        let s = q.snapshot();
+        assert_eq!(s.len(), 3);
+        assert_eq!(
+            (0..4).map(|i| s.get(i)).collect::<Vec<_>>(),
+            vec![Some(1), Some(2), Some(3), None]
+        );
+
+        write(&q, &mut (4..), 3);
+        let s2 = q.snapshot();
+        assert_eq!(s2.len(), 4);
+        assert_eq!(
+            (0..4).map(|i| s2.get(i)).collect::<Vec<_>>(),
+            vec![Some(3), Some(4), Some(5), Some(6)]
+        );
+        // the first two writes of the old snapshot have been overwritten
+        assert_eq!(
+            (0..3).map(|i| s.get(i)).collect::<Vec<_>>(),
+            vec![None, None, Some(3)]
+        );
+    }
+
+    #[test]
+    fn latest_indexed() {
+        let q = QueueClient::<u32>::new_queue(4);
+        write(&q, &mut get_incrementor(), 3);
+        assert_eq!(q.try_latest_write_indexed(), Some((q.latest_index(), 3)));
+        q.push(4);
+        assert_eq!(q.try_latest_write_indexed(), Some((q.latest_index(), 4)));
+    }
+
+    #[test]
+    fn cache_padded_clients() {
+        struct Pair {
+            a: CachePadded<QueueClient<u32>>,
+            b: CachePadded<QueueClient<u32>>,
+        }
+        let align = std::mem::align_of::<CachePadded<QueueClient<u32>>>();
+        assert!(align >= 64);
+        let pair = Pair {
+            a: CachePadded::new(QueueClient::new_queue(4)),
+            b: CachePadded::new(QueueClient::new_queue(4)),
+        };
+        let a = &*pair.a as *const _ as usize;
+        let b = &*pair.b as *const _ as usize;
+        assert!(a / align != b / align);
+        pair.a.push(1);
+        assert_eq!(pair.a.into_inner().latest(), 1);
+    }
+
+    #[test]
+    fn push_with_closure() {
+        #[derive(Debug, Default, Clone, Copy, PartialEq)]
+        struct Big {
+            id: u32,
+            samples: [f64; 32],
+        }
+        let mut q = QueueClient::<Big>::new_queue(4);
+        q.push_with(|| Big {
+            id: 3,
+            samples: [1.5; 32],
+        });
+        let (big, dropped) = q.next().unwrap();
+        assert_eq!(dropped, 0);
+        assert_eq!(big.id, 3);
+        assert!(big.samples.iter().all(|&s| s == 1.5));
+    }
+
+    #[test]
+    fn zero_sized_elements() {
+        #[derive(Debug, Default, Clone, Copy, PartialEq)]
+        struct Tick;
+        let mut q = QueueClient::<Tick>::new_queue(16);
+        let start = q.latest_index();
+        for _ in 0..10 {
+            q.push(Tick);
+        }
+        assert_eq!(q.latest_index() - start, 10);
+        let mut count = 0;
+        while let Some((Tick, dropped)) = q.next() {
+            assert_eq!(dropped, 0);
+            count += 1;
+        }
+        assert_eq!(count, 10);
+
+        // laps are still detected
+        for _ in 0..40 {
+            q.push(Tick);
+        }
+        assert!(q.next().unwrap().1 > 0);
+    }
+
+    #[test]
+    fn builder_validates_size() {
+        let builder = QueueClient::<u32>::builder();
+        assert_eq!(builder.allocated_size(), Err(InvalidSize));
+        assert_eq!(builder.build().err(), Some(InvalidSize));
+        assert_eq!(builder.size(usize::MAX).allocated_size(), Err(InvalidSize));
+        let exact = builder.size(100).exact(true);
+        assert_eq!(exact.allocated_size(), Ok(100));
+        let mut q = exact.fence(WriterFence::SeqCst).build().unwrap();
+        assert_eq!((q.size(), q.writer_fence()), (100, WriterFence::SeqCst));
+        assert_eq!(q.next(), None);
+    }
+
+    #[test]
+    fn from_slice_replays_in_order() {
+        let queue = Queue::from_slice(&[1u32, 2, 3], WriterFence::default());
+        assert_eq!((queue.size(), queue.write_count()), (4, 3));
+        // the leftover slot still holds the default fill
+        assert_eq!(queue.read(3), Ok(0));
+        let first = queue.size();
+        let mut q = QueueClient::from_parts(Arc::new(queue), first);
+        assert_eq!(q.by_ref().collect::<Vec<_>>(), [1, 2, 3]);
+
+        let queue = Queue::from_slice(&[7u32; 5], WriterFence::default());
+        assert_eq!((queue.size(), queue.write_count()), (8, 5));
+        let first = queue.size();
+        let mut q = QueueClient::from_parts(Arc::new(queue), first);
+        assert_eq!(q.by_ref().collect::<Vec<_>>(), [7; 5]);
+    }
+
+    #[test]
+    fn from_slice_empty() {
+        let queue = Queue::<u32>::from_slice(&[], WriterFence::default());
+        assert_eq!((queue.size(), queue.write_count()), (1, 0));
+        assert!(queue.read(queue.size()).is_err());
+        queue.push(4);
+        assert_eq!(queue.read(queue.size()), Ok(4));
+    }
+
+    #[test]
+    fn from_iter_sized_keeps_tail() {
+        let mut q = QueueClient::from_iter_sized(0..10u32, 4);
+        assert_eq!(q.size(), 4);
+        read(&mut q, &mut Chomp(Some(5)), 4);
+        assert_eq!(q.next(), None);
+
+        let mut q = QueueClient::from_iter_sized(0..3u32, 4);
+        assert_eq!(q.next(), Some((0, 0)));
+    }
+
+    #[test]
+    fn watcher_wakes_on_change() {
+        let w = QueueClient::<u32>::new_queue(16);
+        w.push(1);
+        let mut watcher = w.watch();
+        assert!(!watcher.has_changed());
+        assert_eq!(watcher.borrow(), 1);
+
+        let t = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            w.push(2);
+        });
+        watcher.changed();
+        assert_eq!(watcher.borrow(), 2);
+        assert!(!watcher.has_changed());
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn poisoned_at_epoch_overflow() {
+        let mut q = QueueClient::<u32>::new_queue(4);
+        q.queue.fast_forward(SENTINEL_MASK - 4);
+        q.catch_up(q.size());
+        write(&q, &mut get_incrementor(), 4);
+        assert!(!q.is_poisoned());
+        q.push(5);
+        q.push(6);
+        assert!(q.is_poisoned());
+        assert_eq!(q.latest(), 4);
+        assert_eq!(q.latest_write(), 4);
+        assert_eq!(q.try_latest_write(), None);
+        read(&mut q, &mut Chomp::default(), 4);
+        assert_eq!(q.next(), None);
+    }
+
+    #[test]
+    fn latest_timeout_indexed() {
+        let q = QueueClient::<u32>::new_queue(4);
+        let timeout = Duration::from_millis(5);
+        let start = Instant::now();
+        assert_eq!(q.latest_timeout_indexed(timeout), None);
+        assert!(start.elapsed() >= timeout);
+        write(&q, &mut get_incrementor(), 6);
+        assert_eq!(
+            q.latest_timeout_indexed(timeout),
+            Some((q.latest_index(), 6))
+        );
+    }
+
+    #[test]
+    fn push_rate() {
+        let mut q = QueueClient::<u32>::new_queue(16);
+        assert_eq!(q.push_rate(), 0.0);
+        let w = q.clone();
+        let t = thread::spawn(move || {
+            for data in 0..100 {
+                w.push(data);
+                thread::sleep(Duration::from_micros(100));
+            }
+        });
+        t.join().unwrap();
+        // 100 pushes, which took at least 10ms and surely less than a second
+        let rate = q.push_rate();
+        assert!((100.0..=100.0 / 0.01).contains(&rate), "{}", rate);
+        assert_eq!(q.push_rate(), 0.0);
+    }
+
+    #[test]
+    fn cell_read_validated() {
+        let cell = Cell::<u32>::default();
+        assert_eq!(cell.read_validated(0), Ok(0));
+        cell.write(5, 4, 4, WriterFence::Release);
+        assert_eq!(cell.read_validated(4), Ok(5));
+        assert_eq!(cell.read_validated(0), Err(4));
+        assert_eq!(cell.read_validated(8), Err(4));
+        // a write of epoch 8 that has started, but not completed
+        cell.epoch.store(8 | SENTINEL_MASK, SeqCst);
+        assert_eq!(cell.read_validated(4), Err(8 | SENTINEL_MASK));
+        assert_eq!(cell.read_validated(8), Err(8 | SENTINEL_MASK));
+    }
+
+    #[test]
+    fn fork_with_lag() {
+        let q = QueueClient::<u32>::new_queue(16);
+        assert_eq!(q.fork_with_lag(3).next(), None);
+        write(&q, &mut get_incrementor(), 10);
+        let mut lagged = q.fork_with_lag(3);
+        read(&mut lagged, &mut Chomp(Some(7)), 3);
+        assert_eq!(lagged.next(), None);
+
+        let mut clamped = q.fork_with_lag(100);
+        read(&mut clamped, &mut Chomp(Some(0)), 10);
+        assert_eq!(clamped.next(), None);
+    }
+
+    #[test]
+    fn try_clone_lapped() {
+        let mut q = QueueClient::<u32>::new_queue(4);
+        write(&q, &mut get_incrementor(), 3);
+        let mut clone = q.try_clone().unwrap();
+        read(&mut clone, &mut Chomp(Some(0)), 3);
+
+        write(&q, &mut (4..), 2);
+        assert_eq!(q.try_clone().err(), Some(Lapped));
+        // reading catches up, after which cloning works again
+        q.next().unwrap();
+        assert!(q.try_clone().is_ok());
+    }
+
+    #[test]
+    fn next_strict_reports_gap() {
+        let mut q = QueueClient::<u32>::new_queue(4);
+        write(&q, &mut (0..), 2);
+        assert_eq!(q.next_strict(), Ok(Some(0)));
+        write(&q, &mut (2..), 5);
+        let gap = Gap {
+            from: q.size() + 1,
+            to: q.latest_index() + 1 - q.size(),
+        };
+        assert_eq!(gap.to - gap.from, 2);
+        assert_eq!(q.next_strict(), Err(gap));
+        // the read pointer did not move
+        assert_eq!(q.next_strict(), Err(gap));
+        assert_eq!(q.catch_up_to(gap.to), gap.to);
+        for i in 3..7 {
+            assert_eq!(q.next_strict(), Ok(Some(i)));
+        }
+        assert_eq!(q.next_strict(), Ok(None));
+    }
+
+    #[test]
+    fn auto_consumer_keeps_up() {
+        let q = QueueClient::<u32>::new_queue(8);
+        let mut auto = q.clone().resubscribe_on_lap();
+        write(&q, &mut (0..), 20);
+        // lapped, so jumps to the latest write
+        assert_eq!(auto.next(), Some(19));
+        assert_eq!(auto.skipped(), 19);
+        assert_eq!(auto.next(), None);
+
+        let writer = q.clone();
+        let producer = thread::spawn(move || write(&writer, &mut (20..), 100_000));
+        let mut last = 19;
+        while !producer.is_finished() {
+            if let Some(x) = auto.next() {
+                assert!(x > last);
+                last = x;
+            }
+            thread::sleep(Duration::from_micros(50));
+        }
+        producer.join().unwrap();
+        while let Some(x) = auto.next() {
+            assert!(x > last);
+            last = x;
+        }
+        assert_eq!(last, 100_019);
+        assert!(auto.skipped() > 19);
+    }
+
+    #[test]
+    fn auto_consumer_clone_keeps_position() {
+        let q = QueueClient::<u32>::new_queue(8);
+        let mut auto = q.clone().resubscribe_on_lap();
+        write(&q, &mut (0..), 4);
+        assert_eq!(auto.next(), Some(0));
+        assert_eq!(auto.next(), Some(1));
+        let mut clone = auto.clone();
+        assert_eq!(clone.next(), Some(2));
+        assert_eq!(auto.next(), Some(2));
+
+        write(&q, &mut (4..), 20);
+        let mut clone = auto.clone();
+        assert_eq!(clone.next(), Some(23));
+        assert_eq!(auto.next(), Some(23));
+        assert_eq!(clone.skipped(), auto.skipped());
+    }
+
+    #[test]
+    fn parts_round_trip() {
+        let mut q = QueueClient::<u32>::new_queue(4);
+        write(&q, &mut (0..), 3);
+        assert_eq!(q.next(), Some((0, 0)));
+        let (queue, to_read) = q.into_parts();
+        let mut q = QueueClient::from_parts(queue.clone(), to_read);
+        assert_eq!(q.next(), Some((1, 0)));
+
+        // another client sharing the queue resumes at the saved position
+        let mut other = QueueClient::from_parts(queue.clone(), to_read);
+        assert_eq!(other.next(), Some((1, 0)));
+
+        // out of window positions are clamped
+        let mut stale = QueueClient::from_parts(queue.clone(), 0);
+        assert_eq!(stale.next(), Some((0, 0)));
+        let mut ahead = QueueClient::from_parts(queue, usize::MAX);
+        assert_eq!(ahead.next(), None);
+        q.push(3);
+        assert_eq!(ahead.next(), Some((3, 0)));
+    }
+
+    #[test]
+    fn next_blocking_cancelled() {
+        let mut q = QueueClient::<u32>::new_queue(4);
+        let cancel = Arc::new(AtomicBool::new(false));
+        q.push(1);
+        assert_eq!(q.next_blocking_cancellable(&cancel), Some(1));
+
+        let flag = cancel.clone();
+        let reader = thread::spawn(move || q.next_blocking_cancellable(&flag));
+        thread::sleep(Duration::from_millis(10));
+        cancel.store(true, Relaxed);
+        assert_eq!(reader.join().unwrap(), None);
+    }
+
+    #[test]
+    fn reserve_range_publishes_on_commit() {
+        let mut q = QueueClient::<u32>::new_queue(8);
+        let writer = q.clone();
+        q.push(1);
+        let mut range = writer.reserve_range(4);
+        assert_eq!(range.len(), 4);
+        assert_eq!(range.start(), q.size() + 1);
+        for i in 0..4 {
+            range.set(i, 10 + i as u32);
+        }
+        // readers wait for the range before reading later pushes
+        q.push(20);
+        assert_eq!(q.next(), Some((1, 0)));
+        assert_eq!(q.next(), None);
+        assert_eq!(q.read_back(1), Err(ReadError::InProgress));
+        range.commit();
+        for i in 0..4 {
+            assert_eq!(q.next(), Some((10 + i, 0)));
+        }
+        assert_eq!(q.next(), Some((20, 0)));
+
+        // unset writes hold the default, and dropping commits
+        let mut range = writer.reserve_range(2);
+        range.set(1, 5);
+        drop(range);
+        assert_eq!(q.next(), Some((0, 0)));
+        assert_eq!(q.next(), Some((5, 0)));
+        assert_eq!(q.next(), None);
+    }
+
+    #[test]
+    fn wait_until_changed_ignores_same_value() {
+        let q = QueueClient::<u32>::new_queue(4);
+        q.push(1);
+        let writer = q.clone();
+        let pushed = Arc::new(AtomicBool::new(false));
+        let pushed_same = pushed.clone();
+        let producer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            writer.push(1);
+            pushed_same.store(true, SeqCst);
+            thread::sleep(Duration::from_millis(10));
+            writer.push(2);
+        });
+        assert_eq!(q.wait_until_changed(&1), 2);
+        assert!(pushed.load(SeqCst));
+        producer.join().unwrap();
+    }
+
+    #[test]
+    fn len_counts_unread() {
+        let mut q = QueueClient::<u32>::new_queue(4);
+        assert_eq!(q.len(), 0);
+        assert!(q.is_empty());
+        write(&q, &mut (0..), 3);
+        assert_eq!(q.len(), 3);
+        q.next().unwrap();
+        assert_eq!(q.len(), 2);
+        // lapped, so only the history is counted
+        write(&q, &mut (3..), 10);
+        assert_eq!(q.len(), q.size());
+        q.reset();
+        assert_eq!(q.len(), 1);
+        q.next().unwrap();
+        assert!(q.is_empty());
+        QueueClient::skip(&mut q, 5);
+        assert!(q.is_empty());
+    }
+
+    #[test]
+    fn clone_starts_at_oldest() {
+        let mut q = QueueClient::<u32>::new_queue(4);
+        write(&q, &mut (0..), 2);
+        q.next().unwrap();
+        let mut clone = q.clone();
+        let mut kept = q.clone_keeping_position();
+        assert_eq!(clone.next(), Some((0, 0)));
+        assert_eq!(kept.next(), Some((1, 0)));
+
+        write(&q, &mut (2..), 5);
+        let mut clone = q.clone();
+        assert_eq!(clone.next(), Some((3, 0)));
+    }
+
+    #[test]
+    fn arc_split_producer_task() {
+        let (producer, mut consumer) = QueueClient::<u32>::new_queue(1024).into_arc_split();
+        let task = {
+            let producer = producer.clone();
+            thread::spawn(move || {
+                for i in 0..1000 {
+                    producer.push(i);
+                }
+            })
+        };
+        let read: Vec<u32> = (0..1000).map(|_| consumer.next_blocking().0).collect();
+        task.join().unwrap();
+        assert_eq!(read, (0..1000).collect::<Vec<_>>());
+        assert_eq!(Arc::strong_count(&producer), 1);
+        assert_eq!(consumer.client.queue.producer_count(), 1);
+    }
+
+    #[test]
+    fn acks_reach_producer() {
+        let (producer, mut consumer) = QueueClient::<u32>::new_queue(8).split_with_acks(4);
+        let other = producer.clone();
+        assert_eq!(producer.highest_acked(), None);
+        producer.push_batch(&[0, 1, 2]);
+        let (first, _) = consumer.next_with_seq().unwrap();
+        consumer.ack(first);
+        assert_eq!(producer.highest_acked(), Some(first));
+        let mut fork = consumer.clone();
+        let (second, _) = fork.next_with_seq().unwrap();
+        let (third, _) = fork.next_with_seq().unwrap();
+        fork.ack(third);
+        consumer.ack(second);
+        assert_eq!(other.highest_acked(), Some(third));
+
+        // more acks than the ring holds only lose the oldest
+        for i in 0..10 {
+            consumer.ack(first + i);
+        }
+        assert_eq!(producer.highest_acked(), Some(first + 9));
+
+        // a plain split has no ack ring
+        let (producer, consumer) = QueueClient::<u32>::new_queue(8).split();
+        consumer.ack(8);
+        assert_eq!(producer.highest_acked(), None);
+    }
+
+    #[test]
+    fn split_halves_share_queue() {
+        let (producer, mut consumer) = QueueClient::<u32>::new_queue(8).split();
+        producer.push_batch(&[0, 1, 2]);
+        assert_eq!(consumer.next(), Some(0));
+        // a cloned consumer forks at the current position
+        let mut fork = consumer.clone();
+        let other = producer.clone();
+        other.push(3);
+        for i in 1..4 {
+            assert_eq!(consumer.next(), Some(i));
+            assert_eq!(fork.next_with_lag(), Some((i, 0)));
+        }
+        assert_eq!(consumer.next(), None);
+        assert_eq!(consumer.latest(), 3);
+
+        producer.push_batch(&[4, 5, 6, 7, 8, 9, 10, 11, 12, 13]);
+        assert_eq!(consumer.next_with_lag(), Some((7, 3)));
+        assert_eq!(consumer.next(), Some(8));
+    }
+
+    #[test]
+    fn read_races_counted() {
+        let q = QueueClient::<[u64; 32]>::new_queue(2);
+        let stop = Arc::new(AtomicBool::new(false));
+        let writer = q.clone();
+        let stop_writer = stop.clone();
+        let producer = thread::spawn(move || {
+            let mut i = 0;
+            while !stop_writer.load(Relaxed) {
+                writer.push([i; 32]);
+                i += 1;
+            }
+        });
+        let queue: &Queue<_> = q.as_ref();
+        let start = Instant::now();
+        while queue.read_race_count() == 0 && start.elapsed() < Duration::from_secs(10) {
+            // the oldest write is the next to be overwritten
+            let _ = queue.read(q.latest_index() - 1);
+        }
+        stop.store(true, Relaxed);
+        producer.join().unwrap();
+        assert!(queue.read_race_count() > 0);
+    }
+
+    #[test]
+    fn try_push_does_not_wait() {
+        let q = QueueClient::<u32>::new_queue(2);
+        assert_eq!(q.try_push(1), Ok(()));
+        // the write of the next cell's previous lap is still in progress
+        let range = q.reserve_range(1);
+        assert_eq!(q.try_push(2), Ok(()));
+        assert_eq!(q.try_push(3), Err(3));
+        assert_eq!(q.latest_index(), q.size() + 2);
+        drop(range);
+        assert_eq!(q.try_push(3), Ok(()));
+        assert_eq!(q.read_back(0), Ok(3));
+        assert_eq!(q.read_back(1), Ok(2));
+    }
+
+    #[test]
+    fn next_status_in_progress() {
+        let mut q = QueueClient::<u32>::new_queue(4);
+        assert_eq!(q.next_status(), NextStatus::CaughtUp);
+        q.push(1);
+        let writer = q.clone();
+        let range = writer.reserve_range(1);
+        assert_eq!(q.next_status(), NextStatus::Got(1));
+        assert_eq!(q.next_status(), NextStatus::HeadInProgress);
+        drop(range);
+        assert_eq!(q.next_status(), NextStatus::Got(0));
+        assert_eq!(q.next_status(), NextStatus::CaughtUp);
+    }
+
+    #[test]
+    fn const_queue() {
+        let q = ConstQueue::<u32, 8>::new();
+        assert_eq!(q.size(), 8);
+        assert_eq!(q.read_back(0), Err(ReadError::NotYetWritten));
+        for i in 0..10 {
+            q.push(i);
+        }
+        assert_eq!(q.read_back(0), Ok(9));
+        assert_eq!(q.read_back(7), Ok(2));
+        assert_eq!(q.read_back(8), Err(ReadError::Lapped));
+        let mut range = q.reserve_range(2);
+        range.set(0, 10);
+        range.set(1, 11);
+        range.commit();
+        assert_eq!(q.read_latest(), 11);
+        let (idx, data) = q.read_latest_indexed();
+        assert_eq!(q.read(idx), Ok(data));
+    }
+
+    #[test]
+    fn next_wait_parks_until_push() {
+        let (producer, mut consumer) = QueueClient::<u32>::new_queue(4).split();
+        assert_eq!(consumer.next_wait_timeout(Duration::from_millis(10)), None);
+        let pusher = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            producer.push(1);
+            thread::sleep(Duration::from_millis(20));
+            producer.push(2);
+        });
+        assert_eq!(consumer.next_wait(), 1);
+        assert_eq!(consumer.next_wait_timeout(Duration::from_secs(10)), Some(2));
+        pusher.join().unwrap();
+    }
+
+    #[test]
+    fn peek_does_not_advance() {
+        let (producer, mut consumer) = QueueClient::<u32>::new_queue(4).split();
+        assert_eq!(consumer.peek(), None);
+        producer.push_batch(&[0, 1]);
+        assert_eq!(consumer.peek(), Some(0));
+        assert_eq!(consumer.peek(), Some(0));
+        assert_eq!(consumer.next(), Some(0));
+        assert_eq!(consumer.peek(), Some(1));
+        // once overwritten, peek sees nothing and next catches up
+        producer.push_batch(&[2, 3, 4, 5, 6, 7]);
+        assert_eq!(consumer.peek(), None);
+        assert_eq!(consumer.next_with_lag(), Some((5, 4)));
+        assert_eq!(consumer.peek(), Some(6));
+    }
+
+    #[test]
+    fn catch_up_extremes() {
+        let (producer, mut consumer) = QueueClient::<u32>::new_queue(4).split();
+        consumer.catch_up_latest();
+        assert_eq!(consumer.next(), None);
+        producer.push_batch(&[0, 1]);
+        consumer.catch_up_to_oldest();
+        assert_eq!(consumer.next(), Some(0));
+        consumer.catch_up_latest();
+        assert_eq!(consumer.next(), None);
+        producer.push(2);
+        assert_eq!(consumer.next(), Some(2));
+
+        producer.push_batch(&[3, 4, 5, 6, 7, 8, 9]);
+        consumer.catch_up_to_oldest();
+        assert_eq!(consumer.next_with_lag(), Some((6, 0)));
+        consumer.catch_up_latest();
+        assert_eq!(consumer.next(), None);
+    }
+
+    #[test]
+    fn drain_stops_when_empty() {
+        let (producer, mut consumer) = QueueClient::<u32>::new_queue(8).split();
+        assert_eq!(consumer.drain().count(), 0);
+        producer.push_batch(&[0, 1, 2, 3, 4]);
+        assert!(consumer.drain().eq(0..5));
+        assert_eq!(consumer.drain().next(), None);
+        producer.push_batch(&[5, 6]);
+        assert!(consumer.iter_blocking().take(2).eq(5..7));
+        assert_eq!(consumer.next(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "padded-cells")]
+    fn cells_padded() {
+        let align = core::mem::align_of::<CachePadded<u8>>();
+        assert_eq!(core::mem::align_of::<Cell<u32>>(), align);
+        assert_eq!(core::mem::size_of::<Cell<u32>>(), align);
+        assert_eq!(Queue::<u32>::capacity_for_bytes(align * 4), 4);
+    }
+
+    #[test]
+    #[cfg(not(feature = "padded-cells"))]
+    fn cell_layout_per_profile() {
+        // data and epoch, plus the sequence number in debug builds
+        let words = if cfg!(debug_assertions) { 3 } else { 2 };
+        let cell = words * core::mem::size_of::<usize>();
+        assert_eq!(core::mem::size_of::<Cell<usize>>(), cell);
+        assert_eq!(Queue::<usize>::capacity_for_bytes(cell * 4), 4);
+    }
+
+    #[test]
+    fn position_and_lag() {
+        let q = QueueClient::<u32>::new_queue(4);
+        let queue: &Queue<u32> = q.as_ref();
+        assert_eq!(queue.write_count(), 0);
+        let (producer, mut consumer) = q.clone().split();
+        let start = consumer.position();
+        assert_eq!(consumer.lag(), 0);
+        producer.push_batch(&[0, 1, 2]);
+        assert_eq!(queue.write_count(), 3);
+        assert_eq!(consumer.lag(), 3);
+        consumer.next();
+        assert_eq!(consumer.position(), start + 1);
+        assert_eq!(consumer.lag(), 2);
+        producer.push_batch(&[3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(consumer.lag(), 9);
+        assert_eq!(q.latest_index() + 1 - consumer.position(), 9);
+    }
+
+    #[test]
+    fn push_batch_is_contiguous() {
+        const BATCH: u32 = 8;
+        const ROUNDS: u32 = 200;
+        let (pusher, mut q) = QueueClient::<u32>::new_queue(4096).split();
+        let batcher = pusher.clone();
+        let t = thread::spawn(move || {
+            for round in 0..ROUNDS {
+                let batch: Vec<u32> = (round * BATCH..(round + 1) * BATCH).collect();
+                batcher.push_batch(&batch);
+            }
+        });
+        for i in 0..ROUNDS * BATCH {
+            pusher.push(1 << 31 | i);
+        }
+        t.join().unwrap();
+
+        let (mut batched, mut pushed) = (0, 0);
+        while let Some(x) = q.next() {
+            if x & 1 << 31 != 0 {
+                assert_eq!(x & !(1 << 31), pushed);
+                pushed += 1;
+            } else {
+                assert_eq!(x, batched);
+                batched += 1;
+                // the rest of the batch follows without interleaving
+                for _ in 1..BATCH {
+                    assert_eq!(q.next(), Some(batched));
+                    batched += 1;
+                }
+            }
+        }
+        assert_eq!(batched, ROUNDS * BATCH);
+        assert_eq!(pushed, ROUNDS * BATCH);
+    }
+
+    #[test]
+    fn next_n_stops_at_head_and_overtake() {
+        let q = QueueClient::<u32>::new_queue(8);
+        let (producer, mut consumer) = q.split();
+        let mut out = [0; 4];
+        assert_eq!(consumer.next_n(&mut out), 0);
+        producer.push_batch(&[0, 1, 2]);
+        assert_eq!(consumer.next_n(&mut out), 3);
+        assert_eq!(out[..3], [0, 1, 2]);
+
+        // a lapped consumer catches up on the first read, then reads on
+        producer.push_batch(&[3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+        assert_eq!(consumer.next_n(&mut out), 4);
+        assert!(out[0] > 3);
+        assert!(out.windows(2).all(|w| w[1] == w[0] + 1));
+        let mut rest = [0; 8];
+        let n = consumer.next_n(&mut rest);
+        assert_eq!(rest[n - 1], 12);
+        assert_eq!(consumer.next_n(&mut rest), 0);
+    }
+
+    #[test]
+    fn writers_on_a_small_queue() {
+        const WRITERS: u32 = 4;
+        const PUSHES: u32 = 20_000;
+        let mut q = QueueClient::<u32>::new_queue(4);
+        let writers: Vec<_> = (0..WRITERS)
+            .map(|w| {
+                let q = q.clone();
+                thread::spawn(move || {
+                    for i in 0..PUSHES {
+                        q.push(w << 24 | i);
+                    }
+                })
+            })
+            .collect();
+        // each writer's pushes stay in order, however the writers interleave
+        let mut last = [None; WRITERS as usize];
+        let mut check = |x: u32| {
+            let (w, i) = ((x >> 24) as usize, x & 0xff_ffff);
+            assert!(last[w] < Some(i), "{:?} then {}", last[w], i);
+            last[w] = Some(i);
+        };
+        while writers.iter().any(|t| !t.is_finished()) {
+            if let Some((x, _)) = q.next() {
+                check(x);
+            }
+        }
+        for t in writers {
+            t.join().unwrap();
+        }
+        while let Some((x, _)) = q.next() {
+            check(x);
+        }
+        let queue: &Queue<u32> = q.as_ref();
+        assert_eq!(queue.write_count(), (WRITERS * PUSHES) as usize);
+        assert_eq!(q.latest() & 0xff_ffff, PUSHES - 1);
+    }
+
+    #[test]
+    fn try_next_reports_overrun() {
+        let (producer, mut consumer) = QueueClient::<u32>::new_queue(4).split();
+        assert_eq!(consumer.try_next(), Ok(None));
+        producer.push_batch(&[0, 1]);
+        assert_eq!(consumer.try_next(), Ok(Some(0)));
+        producer.push_batch(&[2, 3, 4, 5, 6, 7]);
+        // 1 to 3 were overwritten, and 4 is skipped as a margin for writers
+        assert_eq!(consumer.try_next(), Err(Overrun { skipped: 4 }));
+        assert_eq!(consumer.try_next(), Ok(Some(5)));
+        assert_eq!(consumer.try_next(), Ok(Some(6)));
+        assert_eq!(consumer.try_next(), Ok(Some(7)));
+        assert_eq!(consumer.try_next(), Ok(None));
+    }
+
+    #[test]
+    fn resync_policy_skips_silently() {
+        let (producer, mut consumer) = QueueClient::<u32>::new_queue(4).split();
+        assert_eq!(consumer.overrun_policy(), OverrunPolicy::Error);
+        consumer.set_overrun_policy(OverrunPolicy::Resync);
+        let mut erroring = consumer.clone();
+        erroring.set_overrun_policy(OverrunPolicy::Error);
+        assert_eq!(consumer.clone().overrun_policy(), OverrunPolicy::Resync);
+        producer.push_batch(&[0, 1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(consumer.try_next(), Ok(Some(5)));
+        assert_eq!(erroring.try_next(), Err(Overrun { skipped: 5 }));
+        assert_eq!(erroring.try_next(), Ok(Some(5)));
+    }
+
+    #[test]
+    fn push_checked_refuses_at_the_limit() {
+        let mut q = QueueClient::<u32>::new_queue(4);
+        q.queue.fast_forward(SENTINEL_MASK - 4);
+        assert_eq!(q.writes_remaining(), 4);
+        for i in 0..4 {
+            assert_eq!(q.push_checked(i), Ok(()));
+        }
+        assert_eq!(q.writes_remaining(), 0);
+        assert_eq!(q.push_checked(4), Err(QueueExhausted));
+        assert!(!q.is_poisoned());
+        assert_eq!(q.latest(), 3);
+        q.catch_up_to_oldest();
+        assert_eq!(q.next(), Some((0, 0)));
+        q.push(4);
+        assert!(q.is_poisoned());
+        assert_eq!(q.writes_remaining(), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "async")]
+    fn stream_wakes_on_push() {
+        use core::{pin::Pin, task::Context};
+        use futures_core::Stream;
+
+        let (producer, consumer) = QueueClient::<u32>::new_queue(64).split();
+        let mut stream = consumer.into_stream();
+        let t = thread::spawn(move || {
+            for i in 0..10 {
+                thread::sleep(Duration::from_millis(1));
+                producer.push(i);
+            }
+        });
+        // a minimal executor, parking between polls
+        let mut read = Vec::new();
+        THREAD_WAKER.with(|waker| {
+            let mut cx = Context::from_waker(waker);
+            while read.len() < 10 {
+                match Pin::new(&mut stream).poll_next(&mut cx) {
+                    Poll::Ready(Some(x)) => read.push(x),
+                    Poll::Ready(None) => unreachable!(),
+                    Poll::Pending => thread::park(),
+                }
+            }
+        });
+        t.join().unwrap();
+        assert_eq!(read, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn sequence_numbers() {
+        let (producer, mut consumer) = QueueClient::<u32>::new_queue(4).split();
+        let first = consumer.position();
+        producer.push_batch(&[0, 1]);
+        assert_eq!(consumer.next_with_seq(), Some((first, 0)));
+        assert_eq!(consumer.next_with_seq(), Some((first + 1, 1)));
+        assert_eq!(consumer.next_with_seq(), None);
+        producer.push_batch(&[2, 3, 4, 5, 6, 7]);
+        // the jump shows what was skipped
+        let (seq, data) = consumer.next_with_seq().unwrap();
+        assert!(seq > first + 2);
+        assert_eq!(data as usize, seq - first);
+        let queue: &Queue<u32> = producer.queue.as_ref();
+        assert_eq!(queue.read_with_seq(seq), Ok((seq, data)));
+        assert!(queue.read_with_seq(first).is_err());
+    }
+
+    #[test]
+    fn bounded_push_waits_for_readers() {
+        let mut q = BoundedQueueClient::<u32>::new_queue(4);
+        for i in 0..4 {
+            assert_eq!(q.try_push(i), Ok(()));
+        }
+        assert_eq!(q.try_push(4), Err(4));
+        assert_eq!(q.next(), Some(0));
+        assert_eq!(q.try_push(4), Ok(()));
+
+        // a clone holds back pushes from its own position until dropped
+        let mut slow = q.clone();
+        for i in 1..5 {
+            assert_eq!(q.next(), Some(i));
+        }
+        assert_eq!(q.try_push(5), Err(5));
+        assert_eq!(slow.next(), Some(1));
+        assert_eq!(q.try_push(5), Ok(()));
+        drop(slow);
+        for i in 6..9 {
+            assert_eq!(q.try_push(i), Ok(()));
+        }
+        assert_eq!(q.try_push(9), Err(9));
+    }
+
+    #[test]
+    fn bounded_push_blocks_until_slow_reader_drains() {
+        let mut fast = BoundedQueueClient::<u32>::new_queue(4);
+        let mut slow = fast.clone();
+        assert_eq!(fast.consumer_count(), 2);
+        let producer = fast.producer();
+        let pushed = Arc::new(AtomicUsize::new(0));
+        let count = pushed.clone();
+        let writer = thread::spawn(move || {
+            for i in 0..8 {
+                producer.push(i);
+                count.fetch_add(1, Relaxed);
+            }
+        });
+        for i in 0..4 {
+            assert_eq!(fast.next_wait(), i);
+        }
+        // the slow reader hasn't read anything, so the fifth push waits
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(pushed.load(Relaxed), 4);
+        assert_eq!(fast.next(), None);
+        for i in 0..8 {
+            assert_eq!(slow.next_wait(), i);
+        }
+        writer.join().unwrap();
+        assert_eq!(pushed.load(Relaxed), 8);
+        assert!((4..8).all(|i| fast.next_wait() == i));
+        drop(slow);
+        assert_eq!(fast.consumer_count(), 1);
+    }
+
+    #[test]
+    fn evict_hook_reports_unread_messages() {
+        let mut fast = QueueClient::<u32>::new_queue(4);
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let log = evicted.clone();
+        fast.as_ref()
+            .set_on_evict(move |idx, data| log.lock().unwrap().push((idx, data)));
+        // no client registered, so nothing counts as unread
+        write(&fast, &mut (0..), 6);
+        assert!(evicted.lock().unwrap().is_empty());
+
+        let mut lagging = fast.clone();
+        lagging.register_cursor();
+        let first = lagging.latest_index() + 1 - 4;
+        lagging.catch_up_to(first);
+        fast.catch_up_latest();
+        write(&fast, &mut (6..), 2);
+        // the oldest two, which the lagging client never read
+        assert_eq!(*evicted.lock().unwrap(), [(first, 2), (first + 1, 3)]);
+        assert_eq!(fast.next(), Some((6, 0)));
+
+        // once it has read the rest, overwrites are no longer evictions
+        evicted.lock().unwrap().clear();
+        lagging.catch_up_latest();
+        write(&fast, &mut (8..), 4);
+        assert!(evicted.lock().unwrap().is_empty());
+
+        // a clone keeps the registration, and the fifth push overwrites a
+        // message it hasn't read
+        lagging.catch_up_latest();
+        let clone = lagging.clone_keeping_position();
+        drop(lagging);
+        write(&fast, &mut (12..), 5);
+        assert_eq!(*evicted.lock().unwrap(), [(clone.position(), 12)]);
+        // dropping every registered client stops the reports
+        drop(clone);
+        write(&fast, &mut (17..), 4);
+        assert_eq!(evicted.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn bounded_is_lossless() {
+        let mut q = BoundedQueueClient::<u32>::new_queue(4);
+        let producer = q.producer();
+        let mut other = q.clone();
+        let reader =
+            thread::spawn(move || (0..1000).map(|_| other.next_wait()).collect::<Vec<_>>());
+        let writer = thread::spawn(move || {
+            for i in 0..1000 {
+                producer.push(i);
+            }
+        });
+        let read: Vec<u32> = (0..1000).map(|_| q.next_wait()).collect();
+        writer.join().unwrap();
+        assert_eq!(read, (0..1000).collect::<Vec<_>>());
+        assert_eq!(reader.join().unwrap(), read);
+    }
+
+    #[test]
+    fn clear_in_place() {
+        let mut q = QueueClient::<u32>::new_queue(4);
+        write(&q, &mut get_incrementor(), 6);
+        let other = q.clone();
+        assert!(!q.clear());
+        drop(other);
+        let head = q.latest_index();
+        assert!(q.clear());
+        assert_eq!(q.len(), 0);
+        assert_eq!(q.next(), None);
+        assert_eq!(q.latest(), 0);
+        let queue: &Queue<u32> = q.as_ref();
+        assert_eq!(queue.write_count(), 0);
+        assert_eq!(queue.read_back(0), Err(ReadError::NotYetWritten));
+        assert!(queue.read(head).is_err());
+        q.push(7);
+        assert_eq!(q.next(), Some((7, 0)));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_round_trip() {
+        for q in [
+            Queue::<u32>::new(3, WriterFence::SeqCst),
+            Queue::new_exact(5, WriterFence::Release),
+        ] {
+            let q = Arc::new(q);
+            for i in 0..7 {
+                q.push(i);
+            }
+            let mut before = QueueClient::from_parts(q.clone(), 0);
+            let json = serde_json::to_string(&*q).unwrap();
+            let restored: Queue<u32> = serde_json::from_str(&json).unwrap();
+            assert_eq!(restored.write_count(), 7);
+            assert_eq!(restored.fence, q.fence);
+            assert_eq!(restored.requested_capacity(), q.requested_capacity());
+            let mut after = QueueClient::from_parts(Arc::new(restored), 0);
+            assert_eq!(after.position(), before.position());
+            assert_eq!(
+                after.by_ref().collect::<Vec<_>>(),
+                before.by_ref().collect::<Vec<_>>()
+            );
+        }
+        let bad = r#"{"size":4,"exact":false,"fence":"Release","write_ptr":4,"cells":[[0,0]]}"#;
+        assert!(serde_json::from_str::<Queue<u32>>(bad).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_rejects_bad_epochs() {
+        let parse = |write_ptr: usize, epochs: [usize; 4]| {
+            let cells: Vec<_> = epochs.iter().map(|e| format!("[{},1]", e)).collect();
+            let json = format!(
+                r#"{{"size":4,"exact":false,"fence":"Release","write_ptr":{},"cells":[{}]}}"#,
+                write_ptr,
+                cells.join(",")
+            );
+            serde_json::from_str::<Queue<u32>>(&json)
+        };
+        // two writes into the second lap
+        assert!(parse(10, [8, 8, 4, 4]).is_ok());
+        assert!(parse(10, [8, 0, 4, 4]).is_ok());
+        // not a lap boundary
+        let err = parse(10, [8, 8, 5, 4]).unwrap_err().to_string();
+        assert!(err.contains("cell 2 has epoch 5"), "{}", err);
+        // a lap the write pointer hasn't reached
+        assert!(parse(10, [12, 8, 4, 4]).is_err());
+        // in the current lap, but past the write pointer
+        assert!(parse(10, [8, 8, 8, 4]).is_err());
+    }
+
+    #[test]
+    fn latest_write_gives_up_on_stalled_writer() {
+        let q = QueueClient::<u32>::new_queue(4);
+        q.push(1);
+        assert_eq!(q.try_latest_write_blocking(0), Some(1));
+        // a claimed but uncompleted write stands in for a stalled writer
+        let mut stalled = q.reserve_range(1);
+        stalled.set(0, 2);
+        assert_eq!(q.try_latest_write_blocking(0), None);
+        assert_eq!(q.try_latest_write_blocking(1000), None);
+        let timeout = Duration::from_millis(5);
+        assert_eq!(q.latest_write_timeout(timeout), None);
+        // older completed writes are still found
+        assert_eq!(q.latest_timeout_indexed(timeout), Some((4, 1)));
+        drop(stalled);
+        assert_eq!(q.try_latest_write_blocking(1000), Some(2));
+        assert_eq!(q.latest_write_timeout(timeout), Some(2));
+    }
+
+    #[test]
+    fn consumer_metrics_track_laps() {
+        let mut q = QueueClient::<u32>::new_queue(4);
+        write(&q, &mut (0..), 2);
+        read(&mut q, &mut Chomp::default(), 2);
+        let start = q.size();
+        let metrics = ConsumerMetrics {
+            position: start + 2,
+            lag: 0,
+            dropped: 0,
+            max_lag: 0,
+            reconnects: 0,
+        };
+        assert_eq!(q.consumer_metrics(), metrics);
+        write(&q, &mut (2..), 2);
+        assert_eq!(q.consumer_metrics().lag, 2);
+        assert_eq!(q.consumer_metrics().max_lag, 2);
+
+        // lapped by 10 writes in all
+        write(&q, &mut (4..), 8);
+        let (_, dropped) = q.next().unwrap();
+        let metrics = q.consumer_metrics();
+        assert_eq!(metrics.position, QueueClient::position(&q));
+        assert_eq!((metrics.dropped, metrics.reconnects), (dropped, 1));
+        assert_eq!(metrics.max_lag, 10);
+
+        write(&q, &mut (12..), 20);
+        let before = q.lag();
+        let skipped = match q.try_next() {
+            Err(Overrun { skipped }) => skipped,
+            other => panic!("not lapped: {:?}", other),
+        };
+        let metrics = q.consumer_metrics();
+        assert_eq!(metrics.dropped, dropped + skipped);
+        assert_eq!(metrics.reconnects, 2);
+        assert_eq!(metrics.max_lag, before);
+
+        // a clone starts counting afresh
+        let clone = q.clone_keeping_position();
+        assert_eq!(clone.consumer_metrics().reconnects, 0);
+        assert_eq!(clone.consumer_metrics().position, metrics.position);
+    }
+
+    #[test]
+    fn batch_reads_sample_lag() {
+        let mut q = QueueClient::<u32>::new_queue(256);
+        write(&q, &mut (0..), 128);
+        let mut out = [0; 64];
+        // the 64th read samples the 64 still behind it
+        assert_eq!(q.next_n(&mut out), 64);
+        assert_eq!(q.next_n(&mut out), 64);
+        let metrics = q.consumer_metrics();
+        assert_eq!((metrics.lag, metrics.max_lag), (0, 64));
+    }
+
+    #[test]
+    fn reset_stats_zeroes_counters() {
+        let (producer, mut consumer) = QueueClient::<u32>::new_queue(4).split();
+        for i in 0..10 {
+            producer.push(i);
+        }
+        assert_eq!(consumer.next_with_lag(), Some((7, 7)));
+        let metrics = consumer.consumer_metrics();
+        assert_eq!((metrics.dropped, metrics.reconnects), (7, 1));
+
+        consumer.reset_stats();
+        let metrics = consumer.consumer_metrics();
+        assert_eq!((metrics.dropped, metrics.reconnects), (0, 0));
+        // only the current lag is left
+        assert_eq!(metrics.max_lag, metrics.lag);
+        assert_eq!(consumer.next(), Some(8));
+
+        let queue = Queue::<u32>::new(4, WriterFence::default());
+        queue.read_races.store(3, Relaxed);
+        queue.reset_stats();
+        assert_eq!(queue.read_race_count(), 0);
+    }
+
+    #[test]
+    fn catch_up_to_clamps() {
+        let mut q = QueueClient::<u32>::new_queue(4);
+        write(&q, &mut get_incrementor(), 6);
+        let head = q.latest_index() + 1;
+        assert_eq!(q.catch_up_to(head - 2), head - 2);
+        assert_eq!(q.next(), Some((5, 0)));
+        assert_eq!(q.catch_up_to(0), head - 4);
+        assert_eq!(q.next(), Some((3, 0)));
+        assert_eq!(q.catch_up_to(head + 10), head);
+        assert_eq!(q.next(), None);
+    }
 
-    fn next(&mut self) -> Option<T> {
-        QueueClient::next(self).first()
+    #[test]
+    fn cell_epoch_state() {
+        let cell = Cell::<u32>::default();
+        assert_eq!(cell.current_epoch(), 0);
+        assert!(!cell.is_writing());
+
+        cell.write(1, 4, 4, WriterFence::Release);
+        assert_eq!(cell.current_epoch(), 4);
+        assert!(!cell.is_writing());
+
+        cell.write_with(2, 8, 4, WriterFence::Release, |cell| {
+            assert_eq!(cell.current_epoch(), 8);
+            assert!(cell.is_writing());
+        });
+        assert_eq!(cell.current_epoch(), 8);
+        assert!(!cell.is_writing());
     }
 
-    fn count(self) -> usize {
-        unimplemented!()
+    #[test]
+    fn observe_counts_updates() {
+        let mut q = QueueClient::<u32>::new_queue(4);
+        let empty = Observation {
+            value: None,
+            updates_since_last: 0,
+        };
+        assert_eq!(q.observe(), empty);
+        write(&q, &mut get_incrementor(), 3);
+        assert_eq!(
+            q.observe(),
+            Observation {
+                value: Some(3),
+                updates_since_last: 3
+            }
+        );
+        write(&q, &mut (4..), 7);
+        assert_eq!(
+            q.observe(),
+            Observation {
+                value: Some(10),
+                updates_since_last: 7
+            }
+        );
+        assert_eq!(q.observe().updates_since_last, 0);
     }
 
-    fn nth(&mut self, n: usize) -> Option<T> {
-        QueueClient::skip(self, n);
-        <Self as Iterator>::next(self)
+    #[test]
+    fn write_ptr_on_own_cache_line() {
+        let q = Queue::<u32>::new(4, WriterFence::default());
+        let line = std::mem::align_of::<CachePadded<AtomicUsize>>();
+        let addr = |r: *const u8| r as usize;
+        let write_ptr = addr(&q.write_ptr as *const _ as *const u8);
+        let read_races = addr(&q.read_races as *const _ as *const u8);
+        assert_eq!(write_ptr % line, 0);
+        assert_eq!(read_races % line, 0);
+        assert_ne!(write_ptr / line, read_races / line);
+        for field in [
+            addr(&q.data as *const _ as *const u8),
+            addr(&q.size as *const _ as *const u8),
+            addr(&q.idx_mask as *const _ as *const u8),
+            addr(&q.fence as *const _ as *const u8),
+        ] {
+            assert!(field / line != write_ptr / line);
+            assert!(field / line != read_races / line);
+        }
     }
-}
 
-pub struct QueueReadIter<T: Copy>(QueueClient<T>);
-/// Here, `next` is identical to `QueueClient::next_blocking`. `None` is NEVER
-/// yielded, so `for_each` and similar methods will never terminate.
-impl<T: Copy> Iterator for QueueReadIter<T> {
-    type Item = T;
+    #[test]
+    fn drain_for_limits() {
+        let mut q = QueueClient::<u32>::new_queue(1024);
+        let mut buf = Vec::new();
+        write(&q, &mut get_incrementor(), 1000);
+        assert_eq!(q.drain_for(10, Duration::from_secs(10), &mut buf), 10);
+        assert_eq!(buf, (1..=10).collect::<Vec<_>>());
 
-    fn next(&mut self) -> Option<T> {
-        Some(QueueClient::next_blocking(&mut self.0).0)
+        assert_eq!(q.drain_for(1000, Duration::from_secs(0), &mut buf), 0);
+        assert_eq!(buf.len(), 10);
+
+        assert_eq!(q.drain_for(1000, Duration::from_secs(10), &mut buf), 990);
+        assert_eq!(buf, (1..=1000).collect::<Vec<_>>());
     }
 
-    fn count(self) -> usize {
-        unimplemented!()
+    #[test]
+    fn as_ref_queue() {
+        fn describe(q: impl AsRef<Queue<u32>>) -> (usize, Option<u32>) {
+            let q = q.as_ref();
+            (q.size(), q.try_read_latest())
+        }
+        let client = QueueClient::<u32>::new_queue(4);
+        client.push(3);
+        assert_eq!(describe(&client), (4, Some(3)));
+        let queue = Arc::new(Queue::new(8, WriterFence::default()));
+        queue.push(5);
+        assert_eq!(describe(queue), (8, Some(5)));
     }
 
-    fn nth(&mut self, n: usize) -> Option<T> {
-        QueueClient::skip(&mut self.0, n);
-        self.next()
+    #[test]
+    fn read_back_offsets() {
+        let q = QueueClient::<u32>::new_queue(4);
+        assert_eq!(q.read_back(0), Err(ReadError::NotYetWritten));
+        write(&q, &mut get_incrementor(), 3);
+        assert_eq!(q.read_back(0), Ok(3));
+        assert_eq!(q.read_back(2), Ok(1));
+        assert_eq!(q.read_back(3), Err(ReadError::NotYetWritten));
+        write(&q, &mut (4..), 3);
+        assert_eq!(
+            (0..4).map(|n| q.read_back(n)).collect::<Vec<_>>(),
+            vec![Ok(6), Ok(5), Ok(4), Ok(3)]
+        );
+        assert_eq!(q.read_back(4), Err(ReadError::Lapped));
+        assert_eq!(q.read_back(usize::MAX), Err(ReadError::NotYetWritten));
     }
-}
 
-// only has code for these three widths
-#[cfg(any(
-    target_pointer_width = "64",
-    target_pointer_width = "32",
-    target_pointer_width = "16"
-))]
-fn round_up_to_power_of_two(mut u: usize) -> usize {
-    u -= 1;
-    u |= u >> 1;
-    u |= u >> 2;
-    u |= u >> 4;
-    if cfg!(target_pointer_width = "16")
-        || cfg!(target_pointer_width = "32")
-        || cfg!(target_pointer_width = "64")
-    {
-        u |= u >> 8;
+    #[test]
+    fn new_queue_starts_at_next_write() {
+        let mut q = QueueClient::<u32>::new_queue(4);
+        assert_eq!(QueueClient::position(&q), q.latest_index() + 1);
+        assert_eq!(q.next(), None);
+        q.push(7);
+        assert_eq!(q.next(), Some((7, 0)));
+        assert_eq!(q.next(), None);
     }
-    if cfg!(target_pointer_width = "32") || cfg!(target_pointer_width = "64") {
-        u |= u >> 16;
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn snapshot_slots_marks_holes() {
+        let q = QueueClient::<u32>::new_queue(4);
+        let queue: &Queue<u32> = q.as_ref();
+        assert_eq!(queue.snapshot_slots(), vec![]);
+        write(&q, &mut get_incrementor(), 5);
+        let same = QueueClient::from_iter_sized(1..=5, 4);
+        assert_eq!(queue.snapshot_slots(), same.as_ref().snapshot_slots());
+        let token = queue.reserve_range(1);
+        let start = queue.oldest_valid_index();
+        assert_eq!(
+            queue.snapshot_slots(),
+            vec![
+                (start, Some(3)),
+                (start + 1, Some(4)),
+                (start + 2, Some(5)),
+                (start + 3, None),
+            ]
+        );
+        drop(token);
     }
-    if cfg!(target_pointer_width = "64") {
-        u |= u >> 32;
+
+    #[test]
+    fn read_latest_n_in_order() {
+        let q = QueueClient::<u32>::new_queue(4);
+        let queue: &Queue<u32> = q.as_ref();
+        let out = &mut [0; 8];
+        assert_eq!(queue.read_latest_n(3, out), 0);
+        write(&q, &mut get_incrementor(), 2);
+        assert_eq!(queue.read_latest_n(3, out), 2);
+        assert_eq!(out[..2], [1, 2]);
+        write(&q, &mut (3..), 4);
+        assert_eq!(queue.read_latest_n(8, out), 4);
+        assert_eq!(out[..4], [3, 4, 5, 6]);
+        assert_eq!(queue.read_latest_n(8, &mut out[..2]), 2);
+        assert_eq!(out[..2], [5, 6]);
+        // an in-progress write is passed over, and the window ends at the history
+        let token = queue.reserve_range(1);
+        assert_eq!(queue.read_latest_n(3, out), 3);
+        assert_eq!(out[..3], [4, 5, 6]);
+        drop(token);
     }
-    u += 1;
-    u
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
     #[test]
-    fn rounding() {
-        assert_eq!(round_up_to_power_of_two(1), 1);
-        assert_eq!(round_up_to_power_of_two(5), 8);
-        assert_eq!(round_up_to_power_of_two(11), 16);
-        assert_eq!(round_up_to_power_of_two(15), 16);
-        assert_eq!(round_up_to_power_of_two(16), 16);
-        assert_eq!(round_up_to_power_of_two(17), 32);
-        assert_eq!(round_up_to_power_of_two(28), 32);
-        assert_eq!(round_up_to_power_of_two(56), 64);
-        assert_eq!(round_up_to_power_of_two(45), 64);
-        assert_eq!(round_up_to_power_of_two(100), 128);
-        assert_eq!(round_up_to_power_of_two(128), 128);
-        assert_eq!(round_up_to_power_of_two(423), 512);
-        assert_eq!(round_up_to_power_of_two(1000), 1024);
-        assert_eq!(round_up_to_power_of_two(2000), 2048);
+    fn read_range_replays_history() {
+        let q = QueueClient::<u32>::new_queue(4);
+        let queue: &Queue<u32> = q.as_ref();
+        assert_eq!(queue.oldest_valid_index(), queue.newest_index() + 1);
+        assert_eq!(queue.read_range(0, usize::MAX).count(), 0);
+        write(&q, &mut get_incrementor(), 6);
+        let (oldest, newest) = (queue.oldest_valid_index(), queue.newest_index());
+        assert_eq!(newest - oldest + 1, queue.size());
+        assert_eq!(queue.read(newest), Ok(6));
         assert_eq!(
-            round_up_to_power_of_two(1_152_921_504_606_846_000),
-            1_152_921_504_606_846_976
+            queue.read_range(oldest, newest + 1).collect::<Vec<_>>(),
+            vec![Ok(3), Ok(4), Ok(5), Ok(6)]
         );
+        // out of the window is skipped, not misread from a reused cell
         assert_eq!(
-            round_up_to_power_of_two(9_223_372_036_854_000_000),
-            9_223_372_036_854_775_808
+            queue.read_range(0, usize::MAX).collect::<Vec<_>>(),
+            queue.read_range(oldest, newest + 1).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            queue.read_range(oldest + 2, newest).collect::<Vec<_>>(),
+            vec![Ok(5)]
         );
     }
 
-    fn get_incrementor() -> impl Iterator<Item = u32> {
-        std::iter::successors(Some(1), |n| Some(n + 1))
-    }
+    #[cfg(feature = "log")]
+    mod logging {
+        use super::*;
+        use std::sync::Mutex;
 
-    #[derive(Debug, Default, Clone)]
-    struct Chomp(Option<u32>);
+        static RECORDS: Mutex<Vec<(log::Level, String)>> = Mutex::new(Vec::new());
 
-    impl Chomp {
-        fn eat(&mut self, u: u32) {
-            // dbg!((u, self.0));
-            match self.0 {
-                None => (),
-                Some(x) if x == u - 1 => (),
-                Some(_) => panic!(),
+        struct Capture;
+
+        impl log::Log for Capture {
+            fn enabled(&self, _: &log::Metadata) -> bool {
+                true
             }
-            self.0 = Some(u);
+
+            fn log(&self, record: &log::Record) {
+                let entry = (record.level(), record.args().to_string());
+                RECORDS.lock().unwrap().push(entry);
+            }
+
+            fn flush(&self) {}
         }
-    }
 
-    fn write(q: &QueueClient<u32>, data_iter: &mut impl Iterator<Item = u32>, n: u32) {
-        for _ in 0..n {
-            q.push(data_iter.next().unwrap());
+        fn captured(level: log::Level, needle: &str) -> bool {
+            RECORDS
+                .lock()
+                .unwrap()
+                .iter()
+                .any(|(l, msg)| *l == level && msg.contains(needle))
         }
-    }
 
-    fn read(q: &mut QueueClient<u32>, ch: &mut Chomp, n: u32) {
-        for _ in 0..n {
-            ch.eat(q.next_blocking().0);
+        #[test]
+        fn lap_and_ceiling_logged() {
+            let _ = log::set_logger(&Capture);
+            log::set_max_level(log::LevelFilter::Trace);
+
+            let mut q = QueueClient::<u32>::new_queue(4);
+            write(&q, &mut get_incrementor(), 10);
+            assert_eq!(q.next(), Some((8, 7)));
+            assert!(captured(log::Level::Warn, "skipped 7 messages"));
+
+            let q = QueueClient::<u32>::new_queue(4);
+            q.queue.fast_forward(WRITE_CEILING_WARNING);
+            q.push(1);
+            assert!(captured(log::Level::Error, "3/4"));
+            q.queue.fast_forward(SENTINEL_MASK);
+            q.push(1);
+            assert!(captured(log::Level::Error, "poisoned"));
+        }
+
+        #[test]
+        fn slow_predecessor_logged() {
+            let _ = log::set_logger(&Capture);
+            log::set_max_level(log::LevelFilter::Trace);
+
+            // one cell, so epoch 2 waits on the write of epoch 1
+            let queue = Arc::new(Queue::<u32>::new(1, WriterFence::Release));
+            let writer = queue.clone();
+            let t = std::thread::spawn(move || writer.cell(2).write(2, 2, 1, WriterFence::Release));
+            while !captured(log::Level::Warn, "still waiting for epoch 1") {
+                std::thread::yield_now();
+            }
+            // the late write lets the waiting one finish instead of panicking
+            queue.cell(1).write(1, 1, 1, WriterFence::Release);
+            t.join().unwrap();
+            assert_eq!(queue.cell(2).read_validated(2), Ok(2));
         }
     }
 
     #[test]
-    fn single_threaded_single_client() {
-        let q = &mut QueueClient::new_queue(100);
+    fn exact_size() {
+        let mut q = QueueClient::<u32>::new_exact_queue(1000);
+        assert_eq!(q.size(), 1000);
         let di = &mut get_incrementor();
         let ch = &mut Chomp::default();
-        write(q, di, 20);
-        read(q, ch, 20);
-        write(q, di, 30);
-        read(q, ch, 20);
-        write(q, di, 40);
-        read(q, ch, 50);
+        write(&q, di, 999);
+        read(&mut q, ch, 999);
+        // crosses into the second epoch
+        write(&q, di, 2);
+        read(&mut q, ch, 2);
         assert_eq!(q.next(), None);
-        write(q, di, 1);
-        read(q, ch, 1);
+        assert_eq!(q.read_back(999), Ok(2));
+        assert_eq!(q.read_back(1000), Err(ReadError::Lapped));
+
+        // lap the reader across several epochs
+        write(&q, di, 2500);
+        let (data, dropped) = q.next().unwrap();
+        assert_eq!(data + 998, 3501);
+        assert_eq!(dropped, 1501);
+        read(&mut q, &mut Chomp(Some(data)), 998);
+        assert_eq!(q.next(), None);
+        assert_eq!(q.latest(), 3501);
     }
 
     #[test]
-    fn single_threaded_multi_client() {
-        let q1 = &mut QueueClient::new_queue(100);
-        let q2 = &mut Clone::clone(q1);
-        let di = &mut get_incrementor();
-        let c1 = &mut Chomp::default();
-        let c2 = &mut Chomp::default();
+    fn poll_array_partial() {
+        let mut q = QueueClient::<u32>::new_queue(8);
+        write(&q, &mut get_incrementor(), 2);
+        assert_eq!(q.poll_array::<4>(), ([Some(1), Some(2), None, None], 2));
+        assert_eq!(q.poll_array::<4>(), ([None; 4], 0));
+        write(&q, &mut (3..), 5);
+        assert_eq!(
+            q.poll_array::<4>(),
+            ([Some(3), Some(4), Some(5), Some(6)], 4)
+        );
+    }
 
-        // indivudal
-        write(q1, di, 20);
-        read(q1, c1, 20);
-        read(q2, c2, 20);
+    #[test]
+    #[cfg(debug_assertions)]
+    fn sequence_numbers_match() {
+        for mut q in [
+            QueueClient::<u32>::new_queue(4),
+            QueueClient::<u32>::new_exact_queue(5),
+        ] {
+            let di = &mut get_incrementor();
+            write(&q, di, 3);
+            read(&mut q, &mut Chomp::default(), 3);
+            write(&q, di, 23);
+            assert!(q.next().unwrap().1 > 0);
+            for n in 0..q.size() {
+                q.read_back(n).unwrap();
+            }
+            for idx in q.latest_index() + 1 - q.size()..=q.latest_index() {
+                let cell = q.queue.cell(idx);
+                assert_eq!(cell.seq.load(Relaxed), idx);
+            }
+        }
+    }
 
-        // exchanging
-        write(q1, di, 20);
-        read(q2, c2, 20);
-        write(q2, di, 20);
-        read(q1, c1, 20);
-        read(q2, c2, 20);
-        read(q1, c1, 20);
+    #[test]
+    fn poll_next_waker_woken_by_push() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+        use std::task::{Poll, Wake, Waker};
 
-        assert_eq!(q1.next(), None);
-        write(q1, di, 1);
-        read(q1, c1, 1);
-        read(q2, c2, 1);
+        #[derive(Default)]
+        struct CountingWaker(AtomicUsize);
+        impl Wake for CountingWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
 
-        assert_eq!(q2.next(), None);
-        write(q2, di, 1);
-        read(q2, c2, 1);
-        read(q1, c1, 1);
+        let counter = Arc::new(CountingWaker::default());
+        let waker = Waker::from(counter.clone());
+        let mut q = QueueClient::<u32>::new_queue(8);
+        assert_eq!(q.poll_next_waker(&waker), Poll::Pending);
+        // registering twice does not wake twice
+        assert_eq!(q.poll_next_waker(&waker), Poll::Pending);
+        assert_eq!(counter.0.load(Ordering::SeqCst), 0);
 
-        assert_eq!(q2.next(), None);
-        assert_eq!(q1.next(), None);
+        let writer = q.clone();
+        thread::spawn(move || writer.push(7)).join().unwrap();
+        assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+        assert_eq!(q.poll_next_waker(&waker), Poll::Ready(7));
 
-        write(q1, di, 500);
-        // 562 written so far
-        // default read margin is 1
-        // read 128 back, meaning
-        assert_eq!(q2.next().first(), Some(62 + 500 - 127 + 1));
-        assert_eq!(q1.latest(), 62 + 500);
+        // wakers are only woken once
+        q.push(8);
+        assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+        assert_eq!(q.poll_next_waker(&waker), Poll::Ready(8));
     }
 
-    use std::sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc,
-    };
-    use std::thread;
-    use std::time::Duration;
     #[test]
-    fn multithreaded_single_producer() {
-        let mut q1 = QueueClient::<u32>::new_queue(4000);
-        let mut q2 = q1.clone();
-        let q3 = q1.clone();
-        let q4 = q1.clone();
+    fn rounded_size_matches_new() {
+        for requested in [1, 3, 4, 100, 1000] {
+            let q = QueueClient::<u8>::new_queue(requested);
+            assert_eq!(q.size(), Queue::<u8>::rounded_size(requested));
+        }
+        assert_eq!(Queue::<u8>::rounded_size(100), 128);
+    }
 
-        let messages = q1.size() * 20;
-        let write_delay = 25; // nanos
+    #[test]
+    fn requested_and_allocated_capacity() {
+        let q = QueueClient::<u8>::new_queue(100);
+        assert_eq!(q.capacity(), 128);
+        assert_eq!(q.requested_capacity(), 100);
+        let q = QueueClient::<u8>::new_exact_queue(100);
+        assert_eq!(q.capacity(), 100);
+        assert_eq!(q.requested_capacity(), 100);
+        let q = ConstQueue::<u8, 16>::new();
+        assert_eq!((q.capacity(), q.requested_capacity()), (16, 16));
+        let q = Queue::from_slice(&[1u8, 2, 3], WriterFence::default());
+        assert_eq!((q.capacity(), q.requested_capacity()), (4, 3));
+    }
 
-        // simple reader
-        let t1 = thread::spawn(move || {
-            let mut c1 = Chomp::default();
-            for _ in 0..messages {
-                c1.eat(q1.next_blocking().0);
+    #[test]
+    fn capacity_for_bytes_within_budget() {
+        fn check<T: Copy>() {
+            let cell = std::mem::size_of::<Cell<T>>();
+            assert_eq!(Queue::<T>::capacity_for_bytes(0), 0);
+            assert_eq!(Queue::<T>::capacity_for_bytes(cell - 1), 0);
+            assert_eq!(Queue::<T>::capacity_for_bytes(cell), 1);
+            assert_eq!(Queue::<T>::capacity_for_bytes(cell * 1024), 1024);
+            assert_eq!(Queue::<T>::capacity_for_bytes(cell * 1024 - 1), 512);
+            for budget in [1000, 4096, 1 << 20, 12345678] {
+                let size = Queue::<T>::capacity_for_bytes(budget);
+                assert!(size.is_power_of_two());
+                assert!(size * cell <= budget);
+                assert!(size * 2 * cell > budget);
             }
-        });
-        // reader joins late and will "catch up"
-        let t2 = thread::spawn(move || {
-            let mut c2 = Chomp::default();
-            let skip_first = (q2.size() as u64) * 5;
-            thread::sleep(Duration::from_nanos(write_delay * skip_first));
-            let skip_first = skip_first * 3 / 2; //1.5x margin to ensure we don't just deadlock
-            for _ in 0..(messages - skip_first as usize) {
-                c2.eat(q2.next_blocking().0);
+        }
+        check::<()>();
+        check::<u8>();
+        check::<u64>();
+        check::<[u64; 5]>();
+    }
+
+    #[test]
+    fn closing_iter_ends_after_producer_drop() {
+        let r = QueueClient::<u32>::new_queue(100);
+        let w = r.producer();
+        let messages = 100;
+        let t = thread::spawn(move || {
+            for data in 0..messages {
+                w.push(data);
+                thread::sleep(Duration::from_micros(10));
             }
         });
-        // reader ensures latest is at least monotonic
-        let end_thread = Arc::new(AtomicBool::new(false));
-        let stop = end_thread.clone();
-        let t3 = thread::spawn(move || {
-            let mut last = 0;
-            while !stop.load(Ordering::Relaxed) {
-                let r = q3.latest();
-                assert!(r >= last, "{} >= {}", r, last);
-                last = r;
+        let read: Vec<u32> = r.into_blocking_iter().collect();
+        t.join().unwrap();
+        assert_eq!(read, (0..messages).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn closing_iter_ignores_other_handles() {
+        let (w, r) = QueueClient::<u32>::new_queue(100).split();
+        // none of these push, so they must not keep the iterator going
+        let other_reader = r.clone();
+        let client = QueueClient::from_parts(w.queue.clone(), 0);
+        let watcher = client.watch();
+        let w2 = w.clone();
+        assert_eq!(w.queue.producer_count(), 2);
+        let t = thread::spawn(move || {
+            for data in 0..50 {
+                w.push(data);
+            }
+            drop(w);
+            for data in 50..100 {
+                w2.push(data);
             }
         });
+        let read: Vec<u32> = r.into_blocking_iter().collect();
+        t.join().unwrap();
+        assert_eq!(read, (0..100).collect::<Vec<_>>());
+        assert_eq!(client.as_ref().producer_count(), 0);
+        drop((other_reader, watcher));
+    }
+}
 
-        for data in 0..messages {
-            q4.push(data as u32);
-            thread::sleep(Duration::from_nanos(write_delay));
-        }
+/// Model checked tests of the epoch protocol. Run with
+/// `RUSTFLAGS="--cfg loom" cargo test --lib loom_tests`.
+///
+/// Loom can't see the data in a cell, only the atomics around it, so the
+/// models check which index each read came from rather than its value. In
+/// debug builds, `Queue::read` also checks that index against the one the
+/// cell was written for, which catches torn reads.
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use loom::thread;
 
-        end_thread.store(true, Ordering::Relaxed);
-        t1.join().unwrap();
-        t2.join().unwrap();
-        t3.join().unwrap();
+    /// Tries `reads` reads, returning the index of each that succeeded.
+    fn read_some(client: &mut QueueClient<u32>, reads: usize) -> Vec<usize> {
+        (0..reads)
+            .filter_map(|_| client.next().map(|_| QueueClient::position(client) - 1))
+            .collect()
+    }
+
+    fn increasing(read: &[usize]) -> bool {
+        read.windows(2).all(|w| w[0] < w[1])
+    }
+
+    #[test]
+    fn single_push_read() {
+        loom::model(|| {
+            let mut reader = QueueClient::<u32>::new_queue(2);
+            let writer = reader.clone();
+            let first = reader.position();
+            let t = thread::spawn(move || writer.push(7));
+            let mut read = read_some(&mut reader, 1);
+            t.join().unwrap();
+            read.extend(read_some(&mut reader, 2));
+            assert_eq!(read, [first]);
+        });
+    }
+
+    #[test]
+    fn overtake() {
+        loom::model(|| {
+            let mut reader = QueueClient::<u32>::new_queue(2);
+            let writer = reader.clone();
+            let t = thread::spawn(move || {
+                for i in 1..=3 {
+                    writer.push(i);
+                }
+            });
+            let mut read = read_some(&mut reader, 2);
+            t.join().unwrap();
+            read.extend(read_some(&mut reader, 3));
+            // whatever was skipped, the reads are in order and end at the head
+            assert!(increasing(&read));
+            assert_eq!(read.last(), Some(&reader.latest_index()));
+        });
+    }
+
+    /// A writer completes a write, then checks whether the reader is
+    /// asleep. The reader says it is asleep, then checks the cell. At least
+    /// one of them must see the other, or the reader sleeps through the
+    /// write. Modelled on a bare cell write, as pushes with `std` also fence
+    /// to wake waiting readers, which would hide the difference.
+    fn publish_then_check(fence: WriterFence) {
+        loom::model(move || {
+            // one cell, so its first write is epoch 1
+            let queue = Arc::new(Queue::<u32>::new(1, fence));
+            let asleep = Arc::new(AtomicBool::new(false));
+            let (writer, asleep2) = (queue.clone(), asleep.clone());
+            let t = thread::spawn(move || {
+                writer.cell(1).write(7, 1, 1, fence);
+                asleep2.load(Relaxed)
+            });
+            asleep.store(true, Relaxed);
+            sync::fence(SeqCst);
+            let saw_write = queue.cell(1).read_validated(1).is_ok();
+            let saw_asleep = t.join().unwrap();
+            assert!(saw_write || saw_asleep, "both sides missed each other");
+        });
+    }
+
+    #[test]
+    fn publish_then_check_seqcst() {
+        publish_then_check(WriterFence::SeqCst);
+    }
+
+    #[test]
+    #[should_panic(expected = "both sides missed each other")]
+    fn publish_then_check_release() {
+        publish_then_check(WriterFence::Release);
+    }
+
+    #[test]
+    fn concurrent_writers() {
+        // writers waiting on each other's laps spin, so bound the search
+        let mut model = loom::model::Builder::new();
+        model.preemption_bound = Some(3);
+        model.check(|| {
+            let mut reader = QueueClient::<u32>::new_queue(2);
+            let first = reader.position();
+            let writers: Vec<_> = (0..2)
+                .map(|w| {
+                    let writer = reader.clone();
+                    thread::spawn(move || {
+                        writer.push(w * 2);
+                        writer.push(w * 2 + 1);
+                    })
+                })
+                .collect();
+            let read = read_some(&mut reader, 2);
+            assert!(increasing(&read));
+            for writer in writers {
+                writer.join().unwrap();
+            }
+            // every claimed index was written exactly once, in order, and
+            // the last lap is complete
+            let queue: &Queue<u32> = reader.as_ref();
+            assert_eq!(queue.write_count(), 4);
+            for idx in first + 2..first + 4 {
+                assert_eq!(queue.cell(idx).current_epoch(), queue.epoch(idx));
+                assert!(queue.read(idx).is_ok());
+            }
+        });
+    }
+
+    #[test]
+    fn concurrent_readers() {
+        loom::model(|| {
+            let writer = QueueClient::<u32>::new_queue(2);
+            let first = writer.position();
+            let readers: Vec<_> = (0..2)
+                .map(|_| {
+                    let mut reader = writer.clone();
+                    thread::spawn(move || read_some(&mut reader, 2))
+                })
+                .collect();
+            writer.push(1);
+            writer.push(2);
+            for reader in readers {
+                let read = reader.join().unwrap();
+                assert!(increasing(&read));
+                assert!(read.iter().all(|&idx| idx < first + 2));
+            }
+        });
     }
 }